@@ -1,19 +1,35 @@
 use crate::config::Config;
+use crate::macros::{MacroRunner, MacroStep};
+use crate::output::{OutputError, OutputSink};
+use crate::stick::Stick;
 use gilrs::Button;
-use mouce::MouseActions;
 use std::{
     collections::HashMap,
     path::PathBuf,
     sync::{Arc, Mutex},
+    time::Duration,
 };
-use tauri::{Manager, WebviewWindow};
+use tauri::{Emitter, Manager, WebviewWindow};
 use thiserror::Error;
 
+// name of the layer made up of the top level `actions`
+pub const DEFAULT_LAYER: &str = "default";
+
 #[derive(Clone, Debug)]
 pub struct ActionMap {
     map: HashMap<Button, Vec<Action>>,
 }
 
+impl ActionMap {
+    pub fn get(&self, button: Button) -> Option<&Vec<Action>> {
+        self.map.get(&button)
+    }
+
+    pub fn values(&self) -> impl Iterator<Item = &Vec<Action>> {
+        self.map.values()
+    }
+}
+
 static EMPTY_ACTIONS: Vec<Action> = vec![];
 impl std::ops::Index<Button> for ActionMap {
     type Output = Vec<Action>;
@@ -84,20 +100,95 @@ impl serde::Serialize for ActionMap {
 impl<'d> serde::Deserialize<'d> for ActionMap {
     fn deserialize<D: serde::Deserializer<'d>>(deserializer: D) -> Result<Self, D::Error> {
         let map = HashMap::<String, Vec<Action>>::deserialize(deserializer)?;
-        let mut action_map = HashMap::new();
-        for (button, action) in map {
-            action_map.insert(deserialize_button(button), action);
+        Ok(Self {
+            map: button_map(map)?,
+        })
+    }
+}
+
+// a set of buttons held together
+#[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
+pub struct Chord {
+    #[serde(
+        serialize_with = "serialize_buttons",
+        deserialize_with = "deserialize_buttons"
+    )]
+    pub buttons: Vec<Button>,
+    pub actions: Vec<Action>,
+}
+
+// actions for different ways of pressing a single button
+#[derive(Clone, Debug, Default, serde::Serialize, serde::Deserialize)]
+pub struct Gestures {
+    #[serde(default)]
+    pub tap: Vec<Action>, // pressed and released quickly
+    #[serde(default)]
+    pub hold: Vec<Action>, // held past the hold time, released with the button
+    #[serde(default)]
+    pub double_tap: Vec<Action>, // pressed twice quickly, released with the button
+}
+
+// refire a button's actions while it's held
+#[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
+pub struct Repeat {
+    #[serde(default = "repeat_delay_default")]
+    pub delay_ms: u64, // before the first repeat
+    #[serde(default = "repeat_rate_default")]
+    pub rate: f32, // repeats per second
+}
+
+const fn repeat_delay_default() -> u64 {
+    400
+}
+
+const fn repeat_rate_default() -> f32 {
+    10.0
+}
+
+impl Default for Repeat {
+    fn default() -> Self {
+        Self {
+            delay_ms: repeat_delay_default(),
+            rate: repeat_rate_default(),
+        }
+    }
+}
+
+// faster than this and the repeats pile up between ticks
+pub const MAX_REPEAT_RATE: f32 = 100.0;
+
+impl Repeat {
+    pub fn interval(&self) -> Duration {
+        // validate() keeps the rate in range, this is so a bad one can't spin or panic
+        let rate = if self.rate.is_nan() {
+            repeat_rate_default()
+        } else {
+            self.rate.clamp(0.1, MAX_REPEAT_RATE)
+        };
+        Duration::from_secs_f32(1.0 / rate)
+    }
+
+    pub fn validate(&self) -> Result<(), &'static str> {
+        if !self.rate.is_finite() || self.rate <= 0.0 {
+            return Err("repeat rate must be above 0");
+        }
+        if self.rate > MAX_REPEAT_RATE {
+            return Err("repeat rate can't be above 100 per second");
         }
-        Ok(Self { map: action_map })
+        Ok(())
     }
 }
 
+// gilrs calls the bumpers triggers and the triggers LeftTrigger2/RightTrigger2,
+// the config uses the names on the controller instead
 pub fn serialize_button(button: &Button) -> &'static str {
     match button {
         Button::North => "North",
         Button::East => "East",
         Button::South => "South",
         Button::West => "West",
+        Button::C => "C",
+        Button::Z => "Z",
         Button::DPadUp => "DPadUp",
         Button::DPadRight => "DPadRight",
         Button::DPadDown => "DPadDown",
@@ -110,33 +201,115 @@ pub fn serialize_button(button: &Button) -> &'static str {
         Button::RightThumb => "RightThumb",
         Button::Start => "Start",
         Button::Select => "Select",
-        _ => "Unknown",
+        Button::Mode => "Mode",
+        Button::Unknown => "Unknown",
     }
 }
 
-pub fn deserialize_button(button: String) -> Button {
-    match button.as_str() {
-        "North" => Button::North,
-        "East" => Button::East,
-        "South" => Button::South,
-        "West" => Button::West,
+// every name `serialize_button` writes, listed when a name isn't recognised
+const BUTTON_NAMES: &[&str] = &[
+    "North",
+    "East",
+    "South",
+    "West",
+    "C",
+    "Z",
+    "DPadUp",
+    "DPadRight",
+    "DPadDown",
+    "DPadLeft",
+    "LeftTrigger",
+    "RightTrigger",
+    "LeftBumper",
+    "RightBumper",
+    "LeftThumb",
+    "RightThumb",
+    "Start",
+    "Select",
+    "Mode",
+];
+
+pub fn serialize_buttons<S: serde::Serializer>(
+    buttons: &[Button],
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    serializer.collect_seq(buttons.iter().map(serialize_button))
+}
+
+pub fn deserialize_buttons<'d, D: serde::Deserializer<'d>>(
+    deserializer: D,
+) -> Result<Vec<Button>, D::Error> {
+    let buttons = <Vec<String> as serde::Deserialize>::deserialize(deserializer)?;
+    buttons.iter().map(|button| parse_button(button)).collect()
+}
+
+pub fn serialize_button_map<T: serde::Serialize, S: serde::Serializer>(
+    map: &HashMap<Button, T>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    serializer.collect_map(map.iter().map(|(button, v)| (serialize_button(button), v)))
+}
+
+pub fn deserialize_button_map<'d, T: serde::Deserialize<'d>, D: serde::Deserializer<'d>>(
+    deserializer: D,
+) -> Result<HashMap<Button, T>, D::Error> {
+    let map = <HashMap<String, T> as serde::Deserialize>::deserialize(deserializer)?;
+    button_map(map)
+}
+
+// besides the names `serialize_button` writes, the labels on Xbox style controllers
+// and the gilrs names for the triggers are accepted
+pub fn deserialize_button(button: &str) -> Option<Button> {
+    Some(match button {
+        "North" | "Y" => Button::North,
+        "East" | "B" => Button::East,
+        "South" | "A" => Button::South,
+        "West" | "X" => Button::West,
+        "C" => Button::C,
+        "Z" => Button::Z,
         "DPadUp" => Button::DPadUp,
         "DPadRight" => Button::DPadRight,
         "DPadDown" => Button::DPadDown,
         "DPadLeft" => Button::DPadLeft,
-        "LeftTrigger" => Button::LeftTrigger2,
-        "RightTrigger" => Button::RightTrigger2,
-        "LeftBumper" => Button::LeftTrigger,
-        "RightBumper" => Button::RightTrigger,
-        "LeftThumb" => Button::LeftThumb,
-        "RightThumb" => Button::RightThumb,
+        "LeftTrigger" | "LT" | "LeftTrigger2" => Button::LeftTrigger2,
+        "RightTrigger" | "RT" | "RightTrigger2" => Button::RightTrigger2,
+        "LeftBumper" | "LB" => Button::LeftTrigger,
+        "RightBumper" | "RB" => Button::RightTrigger,
+        "LeftThumb" | "L3" => Button::LeftThumb,
+        "RightThumb" | "R3" => Button::RightThumb,
         "Start" => Button::Start,
-        "Select" => Button::Select,
-        _ => Button::Unknown,
+        "Select" | "Back" => Button::Select,
+        "Mode" | "Guide" => Button::Mode,
+        _ => return None,
+    })
+}
+
+fn parse_button<E: serde::de::Error>(button: &str) -> Result<Button, E> {
+    deserialize_button(button).ok_or_else(|| {
+        E::custom(format!(
+            "unknown button `{}`, expected one of {}",
+            button,
+            BUTTON_NAMES.join(", ")
+        ))
+    })
+}
+
+// a button named twice, e.g. as `A` and `South`, is an error rather than one silently winning
+fn button_map<T, E: serde::de::Error>(map: HashMap<String, T>) -> Result<HashMap<Button, T>, E> {
+    let mut buttons = HashMap::new();
+    for (name, v) in map {
+        let button = parse_button(&name)?;
+        if buttons.insert(button, v).is_some() {
+            return Err(E::custom(format!(
+                "button `{}` is set more than once",
+                serialize_button(&button)
+            )));
+        }
     }
+    Ok(buttons)
 }
 
-#[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
+#[derive(Clone, Debug, PartialEq, serde::Serialize, serde::Deserialize)]
 #[serde(transparent)]
 pub struct LuaScript(String);
 
@@ -151,7 +324,11 @@ impl LuaScript {
     }
 }
 
-#[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
+const fn type_delay_default() -> u64 {
+    10
+}
+
+#[derive(Clone, Debug, PartialEq, serde::Serialize, serde::Deserialize)]
 pub enum Action {
     #[serde(rename = "speed_inc")]
     SpeedInc,
@@ -161,8 +338,14 @@ pub enum Action {
     Rumble,
     #[serde(rename = "toggle_vis")]
     ToggleVis,
+    // switch between controlling the mouse and passing the controller through
+    #[serde(rename = "toggle_mode")]
+    ToggleMode,
     #[serde(rename = "click")]
     Click(MouseButton),
+    // swap what the sticks do, for left handed use
+    #[serde(rename = "swap_sticks")]
+    SwapSticks,
     #[serde(rename = "speed_up")]
     SpeedUp,
     #[serde(rename = "speed_down")]
@@ -179,6 +362,45 @@ pub enum Action {
         #[serde(rename = "path")]
         script: LuaScript,
     },
+    // first press sends the action's down, the next press its up
+    #[serde(rename = "toggle")]
+    Toggle(Box<Action>),
+    // typed off the perform thread, pressing again cancels
+    // only windows can type any character, elsewhere it's limited to what a US keyboard has
+    #[serde(rename = "type_text")]
+    TypeText {
+        text: String,
+        #[serde(default = "type_delay_default")]
+        delay_ms: u64, // between characters
+    },
+    // steps run one after another, pressing again cancels
+    #[serde(rename = "macro")]
+    Macro(Vec<MacroStep>),
+    // activate a layer while the button is held
+    #[serde(rename = "layer")]
+    Layer(String),
+    #[serde(rename = "toggle_layer")]
+    ToggleLayer(String),
+    // switch the layer everything else sits on top of
+    #[serde(rename = "base_layer")]
+    BaseLayer(String),
+}
+
+impl Action {
+    // whether holding a button with a repeat setting refires it,
+    // refiring a toggle or a macro would undo or restart it instead
+    pub fn repeats(&self) -> bool {
+        matches!(self, Action::KeyPress { .. } | Action::Click(_))
+    }
+
+    // the layer a layer action switches to
+    pub fn layer(&self) -> Option<&str> {
+        match self {
+            Action::Layer(name) | Action::ToggleLayer(name) | Action::BaseLayer(name) => Some(name),
+            Action::Toggle(action) => action.layer(),
+            _ => None,
+        }
+    }
 }
 
 //TODO: rumble implementation is stupid (uses two boxes etc)
@@ -187,9 +409,12 @@ where
     R: Fn() -> Result<(), gilrs::ff::Error> + Send + Sync + 'static,
 {
     pub config: Arc<Mutex<Config>>,
-    pub window: WebviewWindow,
+    pub window: Option<WebviewWindow>,
     pub rumble: Option<Rumble<R>>,
     pub lua: Option<&'lua mlua::Lua>,
+    pub output: Arc<dyn OutputSink>,
+    pub macros: MacroRunner,
+    pub binding: String, // what fired the action
 }
 
 #[derive(Error, Debug)]
@@ -197,8 +422,8 @@ pub enum ActionError {
     #[error("Lua error: {0}")]
     Lua(#[from] mlua::Error),
 
-    #[error("Keypress error: {0}")]
-    Keypress(#[from] rdev::SimulateError),
+    #[error("Output error: {0}")]
+    Output(#[from] OutputError),
 
     #[error("Rumble error: {0}")]
     Rumble(#[from] gilrs::ff::Error),
@@ -206,9 +431,6 @@ pub enum ActionError {
     #[error("Tauri error: {0}")]
     Tauri(#[from] tauri::Error),
 
-    #[error("Mouce error: {0}")]
-    Mouce(#[from] mouce::error::Error),
-
     #[error("IO error: {0}")]
     Io(#[from] std::io::Error),
 
@@ -253,7 +475,10 @@ where
             }
             Action::ToggleVis => {
                 log::info!(target: "actions", "toggle vis");
-                let webview_window = &interface.window;
+                let webview_window = interface
+                    .window
+                    .as_ref()
+                    .ok_or(ActionError::Other("no window".to_string()))?;
                 if let Ok(true) = webview_window.is_visible() {
                     webview_window.hide()?;
                 } else {
@@ -261,6 +486,24 @@ where
                     webview_window.set_focus()?;
                 }
             }
+            Action::ToggleMode => {
+                let config = &mut *interface.config.lock().unwrap();
+                config.passthrough = !config.passthrough;
+                log::info!(target: "actions", "passthrough mode: {}", config.passthrough);
+                if let Some(window) = &interface.window {
+                    window.emit("mode_change", config.passthrough)?;
+                }
+            }
+            Action::SwapSticks => {
+                log::info!(target: "actions", "swapping sticks");
+                let config = &mut *interface.config.lock().unwrap();
+                // the default roles depend on the stick so they're filled in before swapping
+                let left = config.stick_role(Stick::Left).clone();
+                let right = config.stick_role(Stick::Right).clone();
+                config.left_stick.role = Some(left);
+                config.right_stick.role = Some(right);
+                std::mem::swap(&mut config.left_stick, &mut config.right_stick);
+            }
             Action::SpeedUp => {
                 log::info!(target: "actions", "speed up");
                 let config = &mut *interface.config.lock().unwrap();
@@ -276,18 +519,85 @@ where
                 let config = &mut *interface.config.lock().unwrap();
                 config.speed = *speed;
             }
-            Action::Click(button) => mouce::Mouse::new().press_button(&button.into())?,
+            Action::Macro(steps) => {
+                log::info!(target: "actions", "macro");
+                for step in steps {
+                    if let MacroStep::Type(text) = step {
+                        crate::text::check_typable(interface.output.as_ref(), text)?;
+                    }
+                }
+                interface
+                    .macros
+                    .toggle(&interface.binding, steps, interface.output.clone());
+            }
+            Action::Toggle(action) => {
+                let (latch, latched) = {
+                    let config = &mut *interface.config.lock().unwrap();
+                    let latch = match config.latched.iter().position(|a| a == &**action) {
+                        Some(i) => {
+                            config.latched.remove(i);
+                            false
+                        }
+                        None => {
+                            config.latched.push((**action).clone());
+                            true
+                        }
+                    };
+                    (latch, config.latched.clone())
+                };
+
+                // the lock is released as the action might need it
+                if latch {
+                    log::info!(target: "actions", "latching {:?}", action);
+                    action.down(interface)?;
+                } else {
+                    log::info!(target: "actions", "unlatching {:?}", action);
+                    action.up(interface)?;
+                }
+                if let Some(window) = &interface.window {
+                    window.emit("latch_change", latched)?;
+                }
+            }
+            Action::TypeText { text, delay_ms } => {
+                log::info!(target: "actions", "typing {} characters", text.chars().count());
+                crate::text::check_typable(interface.output.as_ref(), text)?;
+                let steps = crate::text::type_steps(text, *delay_ms);
+                interface
+                    .macros
+                    .toggle(&interface.binding, &steps, interface.output.clone());
+            }
+            Action::Layer(name) => {
+                log::info!(target: "actions", "layer {} on", name);
+                let config = &mut *interface.config.lock().unwrap();
+                config.active_layers.push(name.clone());
+            }
+            Action::ToggleLayer(name) => {
+                let config = &mut *interface.config.lock().unwrap();
+                if let Some(i) = config.active_layers.iter().rposition(|l| l == name) {
+                    log::info!(target: "actions", "layer {} off", name);
+                    config.active_layers.remove(i);
+                } else {
+                    log::info!(target: "actions", "layer {} on", name);
+                    config.active_layers.push(name.clone());
+                }
+            }
+            Action::BaseLayer(name) => {
+                log::info!(target: "actions", "base layer {}", name);
+                let config = &mut *interface.config.lock().unwrap();
+                config.base_layer = Some(name.clone()).filter(|name| name != DEFAULT_LAYER);
+            }
+            Action::Click(button) => interface.output.press_button(button)?,
             Action::KeyPress { key, modifiers } => {
                 log::info!(target: "actions", "pressing {:?} with modifiers {:?}", key, modifiers);
                 for modifier in modifiers {
-                    rdev::simulate(&rdev::EventType::KeyPress(modifier.into()))?;
+                    interface.output.key_down(modifier.into())?;
                 }
 
-                rdev::simulate(&rdev::EventType::KeyPress(*key))?;
+                interface.output.key_down(*key)?;
             }
             Action::LuaScript { script } => {
-                if let Some(l) = interface.lua {
-                    let config_dir = Config::config_dir(interface.window.app_handle());
+                if let (Some(l), Some(window)) = (interface.lua, &interface.window) {
+                    let config_dir = Config::config_dir(window.app_handle());
                     l.load(script.contents(config_dir)?.as_str()).exec()?;
                 }
             }
@@ -306,26 +616,40 @@ where
                 let config = &mut *interface.config.lock().unwrap();
                 config.speed_mult *= config.speed_up;
             }
-            Action::Click(button) => mouce::Mouse::new().release_button(&button.into())?,
+            Action::Click(button) => interface.output.release_button(button)?,
             Action::KeyPress { key, modifiers } => {
-                rdev::simulate(&rdev::EventType::KeyRelease(*key))?;
+                interface.output.key_up(*key)?;
 
                 for modifier in modifiers.iter().rev() {
-                    rdev::simulate(&rdev::EventType::KeyRelease(modifier.into()))?;
+                    interface.output.key_up(modifier.into())?;
+                }
+            }
+            Action::Layer(name) => {
+                log::info!(target: "actions", "layer {} off", name);
+                let config = &mut *interface.config.lock().unwrap();
+                if let Some(i) = config.active_layers.iter().rposition(|l| l == name) {
+                    config.active_layers.remove(i);
                 }
             }
             Action::LuaScript { .. } => {}
+            Action::ToggleLayer(_)
+            | Action::BaseLayer(_)
+            | Action::Macro(_)
+            | Action::TypeText { .. }
+            | Action::Toggle(_) => {}
             Action::SpeedInc
             | Action::SpeedDec
             | Action::SetSpeed(_)
             | Action::Rumble
-            | Action::ToggleVis => {}
+            | Action::ToggleVis
+            | Action::ToggleMode
+            | Action::SwapSticks => {}
         }
         Ok(())
     }
 }
 
-#[derive(Clone, Copy, Debug, serde::Serialize, serde::Deserialize)]
+#[derive(Clone, Copy, Debug, PartialEq, serde::Serialize, serde::Deserialize)]
 pub enum ModifierKey {
     Alt,
     Ctrl,
@@ -350,7 +674,7 @@ impl From<&ModifierKey> for rdev::Key {
     }
 }
 
-#[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
+#[derive(Clone, Debug, PartialEq, serde::Serialize, serde::Deserialize)]
 pub enum MouseButton {
     Left,
     Right,
@@ -384,3 +708,42 @@ where
         Self { rumble }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn button_names_round_trip() {
+        for &name in BUTTON_NAMES {
+            let button = deserialize_button(name).unwrap();
+            assert_eq!(serialize_button(&button), name);
+        }
+        assert_eq!(deserialize_button("Unknown"), None);
+    }
+
+    #[test]
+    fn aliases_name_the_same_buttons() {
+        let actions: ActionMap =
+            toml::from_str("A = [\"rumble\"]\nLB = [\"speed_inc\"]\nRT = [\"speed_up\"]").unwrap();
+        assert_eq!(actions[Button::South], vec![Action::Rumble]);
+        assert_eq!(actions[Button::LeftTrigger], vec![Action::SpeedInc]);
+        assert_eq!(actions[Button::RightTrigger2], vec![Action::SpeedUp]);
+    }
+
+    #[test]
+    fn unknown_buttons_are_rejected() {
+        let error = toml::from_str::<ActionMap>("Sotuh = [\"rumble\"]").unwrap_err();
+        assert!(error.message().starts_with("unknown button `Sotuh`"));
+
+        let chord = "buttons = [\"LB\", \"Sotuh\"]\nactions = [\"rumble\"]";
+        assert!(toml::from_str::<Chord>(chord).is_err());
+    }
+
+    #[test]
+    fn buttons_named_twice_are_rejected() {
+        let error =
+            toml::from_str::<ActionMap>("A = [\"rumble\"]\nSouth = [\"speed_inc\"]").unwrap_err();
+        assert_eq!(error.message(), "button `South` is set more than once");
+    }
+}
@@ -0,0 +1,198 @@
+use anyhow::{anyhow, Result};
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// how many replaced versions of a file are kept
+const BACKUP_COUNT: usize = 10;
+// folder next to the file that the backups go in
+const BACKUP_DIR: &str = "backups";
+
+#[derive(serde::Serialize, Debug, Clone, PartialEq)]
+pub struct Backup {
+    pub name: String,
+    pub time_ms: u64, // when the file was replaced, ms since the unix epoch
+}
+
+// writes `contents` to `path` without ever leaving a half written file behind,
+// moving the version it replaces into the backups folder
+pub fn replace(path: &Path, contents: &str) -> Result<()> {
+    // the old version is kept byte for byte, even if it isn't valid utf-8
+    match fs::read(path) {
+        Ok(old) if old == contents.as_bytes() => return Ok(()),
+        Ok(old) => backup(path, &old)?,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+        Err(e) => return Err(anyhow!("Could not back up {:?}: {}", path, e)),
+    }
+    write_atomic(path, contents.as_bytes())
+}
+
+// backups of `path`, newest first
+pub fn list(path: &Path) -> Result<Vec<Backup>> {
+    let dir = backup_dir(path);
+    if !dir.exists() {
+        return Ok(Vec::new());
+    }
+
+    let (stem, ext) = name_parts(path);
+    let mut backups = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let name = entry?.file_name().to_string_lossy().into_owned();
+        let time_ms = name
+            .strip_prefix(&format!("{}-", stem))
+            .and_then(|name| name.strip_suffix(&ext))
+            .and_then(|time| time.parse().ok());
+        if let Some(time_ms) = time_ms {
+            backups.push(Backup { name, time_ms });
+        }
+    }
+    backups.sort_by_key(|backup| std::cmp::Reverse(backup.time_ms));
+    Ok(backups)
+}
+
+// only names from `list` are read so nothing outside the backups folder can be
+pub fn read(path: &Path, name: &str) -> Result<String> {
+    let backup = list(path)?
+        .into_iter()
+        .find(|backup| backup.name == name)
+        .ok_or(anyhow!("No backup named {}", name))?;
+    Ok(fs::read_to_string(backup_dir(path).join(backup.name))?)
+}
+
+fn backup(path: &Path, contents: &[u8]) -> Result<()> {
+    let dir = backup_dir(path);
+    fs::create_dir_all(&dir)?;
+
+    let time_ms = SystemTime::now().duration_since(UNIX_EPOCH)?.as_millis();
+    let (stem, ext) = name_parts(path);
+    write_atomic(&dir.join(format!("{}-{}{}", stem, time_ms, ext)), contents)?;
+
+    for old in list(path)?.into_iter().skip(BACKUP_COUNT) {
+        fs::remove_file(dir.join(old.name))?;
+    }
+    Ok(())
+}
+
+// writes to a temporary file and renames it over `path`, which replaces it in one step
+fn write_atomic(path: &Path, contents: &[u8]) -> Result<()> {
+    let mut tmp_name = path.file_name().unwrap_or_default().to_os_string();
+    tmp_name.push(".tmp");
+    let tmp_path = path.with_file_name(tmp_name);
+
+    let mut file = fs::File::create(&tmp_path)?;
+    file.write_all(contents)?;
+    file.sync_all()?;
+    drop(file);
+
+    fs::rename(&tmp_path, path)?;
+    Ok(())
+}
+
+fn backup_dir(path: &Path) -> PathBuf {
+    path.parent()
+        .unwrap_or_else(|| Path::new(""))
+        .join(BACKUP_DIR)
+}
+
+// "config.toml" is split into "config" and ".toml"
+fn name_parts(path: &Path) -> (String, String) {
+    let stem = path
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let ext = path
+        .extension()
+        .map(|ext| format!(".{}", ext.to_string_lossy()))
+        .unwrap_or_default();
+    (stem, ext)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("xouse-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn replace_keeps_the_old_version() {
+        let dir = temp_dir("replace");
+        let path = dir.join("config.toml");
+
+        replace(&path, "speed = 1.0\n").unwrap();
+        assert!(list(&path).unwrap().is_empty());
+
+        replace(&path, "speed = 2.0\n").unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "speed = 2.0\n");
+        assert!(!dir.join("config.toml.tmp").exists());
+
+        let backups = list(&path).unwrap();
+        assert_eq!(backups.len(), 1);
+        assert_eq!(read(&path, &backups[0].name).unwrap(), "speed = 1.0\n");
+
+        // nothing changed so nothing is backed up
+        replace(&path, "speed = 2.0\n").unwrap();
+        assert_eq!(list(&path).unwrap().len(), 1);
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn replace_backs_up_files_that_arent_utf8() {
+        let dir = temp_dir("bytes");
+        let path = dir.join("config.toml");
+        fs::write(&path, b"text = \"caf\xe9\"\n").unwrap();
+
+        replace(&path, "speed = 1.0\n").unwrap();
+
+        let backups = list(&path).unwrap();
+        assert_eq!(backups.len(), 1);
+        let backup = fs::read(dir.join(BACKUP_DIR).join(&backups[0].name)).unwrap();
+        assert_eq!(backup, b"text = \"caf\xe9\"\n");
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn old_backups_are_removed() {
+        let dir = temp_dir("rotate");
+        let path = dir.join("config.toml");
+        fs::create_dir_all(dir.join(BACKUP_DIR)).unwrap();
+        for time in 0..BACKUP_COUNT {
+            fs::write(
+                dir.join(BACKUP_DIR).join(format!("config-{}.toml", time)),
+                "",
+            )
+            .unwrap();
+        }
+        fs::write(dir.join(BACKUP_DIR).join("notes.txt"), "").unwrap();
+
+        fs::write(&path, "speed = 1.0\n").unwrap();
+        replace(&path, "speed = 2.0\n").unwrap();
+
+        let backups = list(&path).unwrap();
+        assert_eq!(backups.len(), BACKUP_COUNT);
+        assert!(backups.iter().all(|backup| backup.name != "config-0.toml"));
+        assert!(dir.join(BACKUP_DIR).join("notes.txt").exists());
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn read_only_finds_backups() {
+        let dir = temp_dir("read");
+        let path = dir.join("config.toml");
+        fs::write(&path, "speed = 1.0\n").unwrap();
+        replace(&path, "speed = 2.0\n").unwrap();
+
+        assert!(read(&path, "../config.toml").is_err());
+        assert!(read(&path, "config-nope.toml").is_err());
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
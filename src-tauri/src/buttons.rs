@@ -0,0 +1,350 @@
+use crate::actions::{serialize_button, Action, Chord};
+use crate::config::Config;
+use gilrs::Button;
+use std::collections::{HashMap, HashSet};
+use std::time::{Duration, Instant};
+
+// the binding that fired, which tells running macros apart, and its actions
+#[derive(Debug, Clone)]
+pub enum Fired {
+    Down(String, Vec<Action>),
+    Up(String, Vec<Action>),
+}
+
+// works out which actions a button press or release fires
+//
+// a chord fires when its last button is pressed and takes precedence over
+// the single button bindings of all its buttons. buttons that are part of a
+// chord wait up to `chord_timeout_ms` before firing on their own, so they can
+// be suppressed if the chord completes
+//
+// buttons with gestures don't fire on press, they wait to see whether they
+// are tapped, held or double tapped. gestures are only used while no active
+// layer binds the button
+//
+// buttons with a repeat setting release and press their key presses and
+// clicks again on every tick past the next repeat time while they are held
+#[derive(Debug, Default)]
+pub struct ButtonTracker {
+    pressed: HashSet<Button>,
+    pending: HashMap<Button, Instant>, // waiting to see if they become a chord
+    held: HashMap<Button, (String, Vec<Action>)>, // actions that are down, released with the button
+    chords: Vec<Chord>,                // chords that are down
+    consumed: HashSet<Button>,         // buttons held as part of a chord
+    gestures: HashMap<Button, GestureState>,
+    repeats: HashMap<Button, Instant>, // when held buttons next refire
+}
+
+#[derive(Debug, Clone, Copy)]
+enum GestureState {
+    Pressed(Instant),  // waiting to see if it's held
+    Released(Instant), // tapped once, waiting for a second tap
+}
+
+impl ButtonTracker {
+    // lets go of everything that's down and forgets the rest,
+    // for when the gamepad holding the buttons goes away
+    pub fn release_all(&mut self) -> Vec<Fired> {
+        let mut fired = self
+            .held
+            .drain()
+            .map(|(_, (binding, actions))| Fired::Up(binding, actions))
+            .collect::<Vec<_>>();
+        fired.extend(
+            self.chords
+                .drain(..)
+                .map(|chord| Fired::Up(chord_binding(&chord), chord.actions)),
+        );
+        *self = Self::default();
+        fired
+    }
+
+    pub fn is_pressed(&self, button: Button) -> bool {
+        self.pressed.contains(&button)
+    }
+
+    pub fn press(&mut self, button: Button, now: Instant, config: &Config) -> Vec<Fired> {
+        let mut fired = vec![];
+        self.pressed.insert(button);
+
+        if let Some(chord) = self.completed_chord(button, config) {
+            for member in &chord.buttons {
+                // suppress the single button bindings
+                self.pending.remove(member);
+                self.gestures.remove(member);
+                self.repeats.remove(member);
+                if let Some((binding, actions)) = self.held.remove(member) {
+                    fired.push(Fired::Up(binding, actions));
+                }
+                self.consumed.insert(*member);
+            }
+            fired.push(Fired::Down(chord_binding(&chord), chord.actions.clone()));
+            self.chords.push(chord);
+            return fired;
+        }
+
+        if config.passthrough {
+            return fired;
+        }
+
+        if let Some(gestures) = config.button_gestures(button) {
+            let double_tap = Duration::from_millis(config.double_tap_ms);
+            match self.gestures.remove(&button) {
+                Some(GestureState::Released(at))
+                    if now.saturating_duration_since(at) <= double_tap =>
+                {
+                    let binding = gesture_binding(button, "double_tap");
+                    self.held
+                        .insert(button, (binding.clone(), gestures.double_tap.clone()));
+                    fired.push(Fired::Down(binding, gestures.double_tap.clone()));
+                }
+                previous => {
+                    if let Some(GestureState::Released(_)) = previous {
+                        // the first tap was never followed up
+                        fired.extend(tap(button, &gestures.tap));
+                    }
+                    self.gestures.insert(button, GestureState::Pressed(now));
+                }
+            }
+            return fired;
+        }
+
+        if config.button_actions(button).is_empty() {
+            return fired;
+        }
+
+        if chords(config).any(|chord| chord.buttons.contains(&button)) {
+            self.pending.insert(button, now);
+        } else {
+            fired.push(self.hold(button, now, config));
+        }
+        fired
+    }
+
+    // presses the button's actions and keeps them down until it's released
+    fn hold(&mut self, button: Button, since: Instant, config: &Config) -> Fired {
+        if let Some(repeat) = config.repeat.get(&button) {
+            let delay = Duration::from_millis(repeat.delay_ms);
+            self.repeats.insert(button, since + delay);
+        }
+        let (binding, actions) = button_binding(button, config);
+        self.held.insert(button, (binding.clone(), actions.clone()));
+        Fired::Down(binding, actions)
+    }
+
+    pub fn release(&mut self, button: Button, now: Instant, config: &Config) -> Vec<Fired> {
+        let mut fired = vec![];
+        self.pressed.remove(&button);
+        self.repeats.remove(&button);
+
+        if self.consumed.remove(&button) {
+            // releasing any button of a chord releases the chord
+            let (released, down) = std::mem::take(&mut self.chords)
+                .into_iter()
+                .partition::<Vec<_>, _>(|chord| chord.buttons.contains(&button));
+            self.chords = down;
+            fired.extend(
+                released
+                    .into_iter()
+                    .map(|chord| Fired::Up(chord_binding(&chord), chord.actions)),
+            );
+        } else if self.pending.remove(&button).is_some() {
+            // released before the chord timeout so it was a plain press
+            let (binding, actions) = button_binding(button, config);
+            fired.push(Fired::Down(binding.clone(), actions.clone()));
+            fired.push(Fired::Up(binding, actions));
+        } else if let Some((binding, actions)) = self.held.remove(&button) {
+            fired.push(Fired::Up(binding, actions));
+        } else if let Some(GestureState::Pressed(_)) = self.gestures.get(&button) {
+            self.gestures.remove(&button);
+            if let Some(gestures) = config.gestures.get(&button).filter(|_| !config.passthrough) {
+                if gestures.double_tap.is_empty() {
+                    fired.extend(tap(button, &gestures.tap));
+                } else {
+                    self.gestures.insert(button, GestureState::Released(now));
+                }
+            }
+        }
+        fired
+    }
+
+    // fires the buttons that have waited too long to become a chord,
+    // the gestures whose timing has run out and the held buttons due to repeat
+    pub fn tick(&mut self, now: Instant, config: &Config) -> Vec<Fired> {
+        let mut fired = self.tick_gestures(now, config);
+        let timeout = Duration::from_millis(config.chord_timeout_ms);
+
+        let expired = self
+            .pending
+            .iter()
+            .filter(|(_, &since)| now.saturating_duration_since(since) >= timeout)
+            .map(|(&button, &since)| (button, since))
+            .collect::<Vec<_>>();
+
+        for (button, since) in expired {
+            self.pending.remove(&button);
+            if config.passthrough {
+                continue;
+            }
+            fired.push(self.hold(button, since, config));
+        }
+
+        fired.extend(self.tick_repeats(now, config));
+        fired
+    }
+
+    // the next time `tick` has something to fire
+    pub fn next_deadline(&self, config: &Config) -> Option<Instant> {
+        let chord_timeout = Duration::from_millis(config.chord_timeout_ms);
+        let hold_time = Duration::from_millis(config.hold_time_ms);
+        // taps fire once the double tap window has passed, not when it ends
+        let double_tap = Duration::from_millis(config.double_tap_ms + 1);
+
+        let pending = self.pending.values().map(|&since| since + chord_timeout);
+        let gestures = self
+            .gestures
+            .iter()
+            .filter_map(|(button, state)| match *state {
+                GestureState::Pressed(since) => config
+                    .gestures
+                    .get(button)
+                    .filter(|gestures| !gestures.hold.is_empty())
+                    .map(|_| since + hold_time),
+                GestureState::Released(at) => Some(at + double_tap),
+            });
+        let repeats = self
+            .repeats
+            .values()
+            .copied()
+            .filter(|_| !config.passthrough);
+        pending.chain(gestures).chain(repeats).min()
+    }
+
+    fn tick_repeats(&mut self, now: Instant, config: &Config) -> Vec<Fired> {
+        let mut fired = vec![];
+        if config.passthrough {
+            return fired;
+        }
+
+        let held = &self.held;
+        self.repeats.retain(|button, next| {
+            if now < *next {
+                return true;
+            }
+            let (repeat, (binding, actions)) = match (config.repeat.get(button), held.get(button)) {
+                (Some(repeat), Some(held)) => (repeat, held),
+                // the repeat was removed from the config
+                _ => return false,
+            };
+            let actions = actions
+                .iter()
+                .filter(|action| action.repeats())
+                .cloned()
+                .collect::<Vec<_>>();
+            if !actions.is_empty() {
+                fired.push(Fired::Up(binding.clone(), actions.clone()));
+                fired.push(Fired::Down(binding.clone(), actions));
+            }
+
+            // if the loop fell behind skip the missed repeats instead of bursting
+            *next += repeat.interval();
+            if *next <= now {
+                *next = now + repeat.interval();
+            }
+            true
+        });
+        fired
+    }
+
+    fn tick_gestures(&mut self, now: Instant, config: &Config) -> Vec<Fired> {
+        let mut fired = vec![];
+        let hold_time = Duration::from_millis(config.hold_time_ms);
+        let double_tap = Duration::from_millis(config.double_tap_ms);
+
+        for (button, state) in std::mem::take(&mut self.gestures) {
+            let gestures = match config.gestures.get(&button) {
+                Some(gestures) if !config.passthrough => gestures,
+                _ => continue,
+            };
+            match state {
+                GestureState::Pressed(since)
+                    if !gestures.hold.is_empty()
+                        && now.saturating_duration_since(since) >= hold_time =>
+                {
+                    let binding = gesture_binding(button, "hold");
+                    self.held
+                        .insert(button, (binding.clone(), gestures.hold.clone()));
+                    fired.push(Fired::Down(binding, gestures.hold.clone()));
+                }
+                GestureState::Released(at) if now.saturating_duration_since(at) > double_tap => {
+                    fired.extend(tap(button, &gestures.tap));
+                }
+                state => {
+                    self.gestures.insert(button, state);
+                }
+            }
+        }
+        fired
+    }
+
+    // the largest chord completed by pressing `button`
+    fn completed_chord(&self, button: Button, config: &Config) -> Option<Chord> {
+        let escape = escape_chord(config);
+        let user_chords = config.chords.iter().filter(|_| !config.passthrough);
+
+        std::iter::once(&escape)
+            .chain(user_chords)
+            .filter(|chord| {
+                !chord.buttons.is_empty()
+                    && chord.buttons.contains(&button)
+                    && chord.buttons.iter().all(|b| self.pressed.contains(b))
+                    && !chord.buttons.iter().any(|b| self.consumed.contains(b))
+            })
+            .fold(None, |best: Option<&Chord>, chord| match best {
+                Some(best) if best.buttons.len() >= chord.buttons.len() => Some(best),
+                _ => Some(chord),
+            })
+            .cloned()
+    }
+}
+
+fn escape_chord(config: &Config) -> Chord {
+    Chord {
+        buttons: config.escape_chord.clone(),
+        actions: vec![Action::ToggleMode],
+    }
+}
+
+// presses and releases a tap gesture's actions
+fn tap(button: Button, actions: &[Action]) -> [Fired; 2] {
+    let binding = gesture_binding(button, "tap");
+    [
+        Fired::Down(binding.clone(), actions.to_vec()),
+        Fired::Up(binding, actions.to_vec()),
+    ]
+}
+
+// the button's actions in the topmost layer that binds it, named after that layer
+fn button_binding(button: Button, config: &Config) -> (String, Vec<Action>) {
+    let (layer, actions) = config.button_binding(button);
+    let binding = format!("{}.{}", layer, serialize_button(&button));
+    (binding, actions.clone())
+}
+
+fn gesture_binding(button: Button, gesture: &str) -> String {
+    format!("gesture.{}.{}", serialize_button(&button), gesture)
+}
+
+fn chord_binding(chord: &Chord) -> String {
+    let buttons = chord
+        .buttons
+        .iter()
+        .map(serialize_button)
+        .collect::<Vec<_>>();
+    format!("chord.{}", buttons.join("+"))
+}
+
+// every chord including the escape chord
+fn chords(config: &Config) -> impl Iterator<Item = Chord> + '_ {
+    std::iter::once(escape_chord(config)).chain(config.chords.iter().cloned())
+}
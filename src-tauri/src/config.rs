@@ -1,6 +1,16 @@
-use crate::actions::ActionMap;
+use crate::actions::{
+    deserialize_button_map, deserialize_buttons, serialize_button_map, serialize_buttons, Action,
+    ActionMap, Chord, Gestures, Repeat, DEFAULT_LAYER,
+};
+use crate::backups::{self, Backup};
+use crate::input::GamepadInfo;
+use crate::roles::StickRole;
+use crate::stick::{Stick, StickConfig};
+use crate::triggers::{Trigger, TriggerConfig};
 use anyhow::{anyhow, Result};
-use std::{io::Write, path::{Path, PathBuf}};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
 use tauri::{AppHandle, Manager};
 
 #[derive(serde::Serialize, serde::Deserialize, Debug, Clone)]
@@ -15,17 +25,121 @@ pub struct Config {
     pub speed_down: f32, // speed down multiplier
     #[serde(default = "speed_step_default")]
     pub speed_step: f32, // speed increment
+    #[serde(default = "tick_rate_default")]
+    pub tick_rate: u32, // movement ticks per second while a stick is pushed
+    #[serde(default = "scroll_speed_default")]
+    pub scroll_speed: f32, // wheel notches per second with the scroll stick fully pushed
 
     #[serde(skip, default)]
-    pub gamepad_id: Option<gilrs::GamepadId>, // gamepad id
+    pub passthrough: bool, // controller mode, bindings and movement are suspended
+    #[serde(
+        default = "escape_chord_default",
+        serialize_with = "serialize_buttons",
+        deserialize_with = "deserialize_buttons"
+    )]
+    pub escape_chord: Vec<gilrs::Button>, // buttons held together to toggle passthrough
+
+    #[serde(skip, default)]
+    pub gamepad_id: Option<usize>, // gamepad id
+    #[serde(default)]
+    pub gamepad_uuid: Option<String>, // uuid of the chosen gamepad
+    #[serde(skip, default)]
+    pub gamepads: Vec<GamepadInfo>, // currently connected gamepads
+
+    #[serde(default)]
+    pub left_stick: StickConfig, // cursor stick settings
+    #[serde(default)]
+    pub right_stick: StickConfig, // scroll stick settings
+    #[serde(default)]
+    pub left_trigger: TriggerConfig,
+    #[serde(default)]
+    pub right_trigger: TriggerConfig,
 
     #[serde(default)]
     pub actions: ActionMap, // map of actions to button presses
+    #[serde(default)]
+    pub layers: HashMap<String, ActionMap>, // named layers of actions on top of `actions`
+    #[serde(skip, default)]
+    pub active_layers: Vec<String>, // layers turned on, last is on top
+    #[serde(default)]
+    pub base_layer: Option<String>, // layer below the active ones, `actions` if none
+
+    #[serde(skip, default)]
+    pub latched: Vec<Action>, // toggled actions that are currently down
+
+    #[serde(default)]
+    pub chords: Vec<Chord>, // actions for buttons pressed together
+    #[serde(default = "chord_timeout_default")]
+    pub chord_timeout_ms: u64, // how long a chord button waits before acting alone
+    #[serde(
+        default,
+        serialize_with = "serialize_button_map",
+        deserialize_with = "deserialize_button_map"
+    )]
+    pub gestures: HashMap<gilrs::Button, Gestures>, // replace the button's actions in `actions`
+    #[serde(default = "hold_time_default")]
+    pub hold_time_ms: u64, // how long a button is pressed before it counts as held
+    #[serde(default = "double_tap_default")]
+    pub double_tap_ms: u64, // how long to wait for the second tap of a double tap
+    #[serde(
+        default,
+        serialize_with = "serialize_button_map",
+        deserialize_with = "deserialize_button_map"
+    )]
+    pub repeat: HashMap<gilrs::Button, Repeat>, // buttons that refire while held
+
+    #[serde(skip, default)]
+    pub load_error: Option<ConfigError>, // why config.toml couldn't be used
 
     #[serde(skip)]
     config_dir: Option<PathBuf>,
 }
 
+// what's wrong with a config file, lines and columns start at 1
+#[derive(serde::Serialize, Debug, Clone, PartialEq)]
+pub struct ConfigError {
+    pub message: String,
+    pub line: Option<usize>,
+    pub column: Option<usize>,
+}
+
+impl ConfigError {
+    fn new(message: &str, config_text: &str, offset: Option<usize>) -> Self {
+        let position = offset.map(|offset| {
+            let before = config_text.get(..offset).unwrap_or(config_text);
+            let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+            (
+                before.matches('\n').count() + 1,
+                before[line_start..].chars().count() + 1,
+            )
+        });
+        Self {
+            message: message.to_string(),
+            line: position.map(|(line, _)| line),
+            column: position.map(|(_, column)| column),
+        }
+    }
+}
+
+impl ConfigError {
+    // a config file that's there but can't be read, like one that isn't utf-8
+    pub fn unreadable(error: &std::io::Error) -> Self {
+        Self::new(&format!("Could not read config.toml: {}", error), "", None)
+    }
+}
+
+impl std::fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.message)?;
+        if let (Some(line), Some(column)) = (self.line, self.column) {
+            write!(f, " at line {}, column {}", line, column)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
 const fn speed_default() -> f32 {
     70.0
 }
@@ -42,6 +156,33 @@ const fn speed_step_default() -> f32 {
     5.0
 }
 
+const fn tick_rate_default() -> u32 {
+    250
+}
+
+const MIN_TICK_RATE: u32 = 10;
+const MAX_TICK_RATE: u32 = 1000;
+
+const fn scroll_speed_default() -> f32 {
+    20.0
+}
+
+const fn chord_timeout_default() -> u64 {
+    50
+}
+
+const fn hold_time_default() -> u64 {
+    300
+}
+
+const fn double_tap_default() -> u64 {
+    250
+}
+
+fn escape_chord_default() -> Vec<gilrs::Button> {
+    vec![gilrs::Button::Select, gilrs::Button::Start]
+}
+
 impl Default for Config {
     fn default() -> Self {
         Self {
@@ -50,14 +191,107 @@ impl Default for Config {
             speed_up: speed_up_default(),
             speed_down: speed_up_default(),
             speed_step: speed_step_default(),
+            tick_rate: tick_rate_default(),
+            scroll_speed: scroll_speed_default(),
+            passthrough: false,
+            escape_chord: escape_chord_default(),
             gamepad_id: None,
+            gamepad_uuid: None,
+            gamepads: Vec::new(),
+            left_stick: StickConfig::default(),
+            right_stick: StickConfig::default(),
+            left_trigger: TriggerConfig::default(),
+            right_trigger: TriggerConfig::default(),
             actions: ActionMap::default(),
+            layers: HashMap::new(),
+            active_layers: Vec::new(),
+            base_layer: None,
+            latched: Vec::new(),
+            chords: Vec::new(),
+            chord_timeout_ms: chord_timeout_default(),
+            gestures: HashMap::new(),
+            hold_time_ms: hold_time_default(),
+            double_tap_ms: double_tap_default(),
+            repeat: HashMap::new(),
+            load_error: None,
             config_dir: None,
         }
     }
 }
 
 impl Config {
+    // time between movement ticks
+    pub fn tick_time(&self) -> Duration {
+        let rate = self.tick_rate.clamp(MIN_TICK_RATE, MAX_TICK_RATE);
+        Duration::from_secs_f64(1.0 / rate as f64)
+    }
+
+    // the actions for a button in the topmost layer that binds it
+    pub fn button_actions(&self, button: gilrs::Button) -> &Vec<Action> {
+        self.button_binding(button).1
+    }
+
+    // the actions for a button and the name of the layer they came from
+    pub fn button_binding(&self, button: gilrs::Button) -> (&str, &Vec<Action>) {
+        let layers = self
+            .active_layers
+            .iter()
+            .rev()
+            .chain(self.base_layer.iter());
+        for name in layers {
+            if let Some(actions) = self.layers.get(name).and_then(|layer| layer.get(button)) {
+                return (name, actions);
+            }
+        }
+        (DEFAULT_LAYER, &self.actions[button])
+    }
+
+    // gestures only belong to `actions`, so a layer that binds the button takes over from them
+    pub fn button_gestures(&self, button: gilrs::Button) -> Option<&Gestures> {
+        self.gestures
+            .get(&button)
+            .filter(|_| self.button_binding(button).0 == DEFAULT_LAYER)
+    }
+
+    pub fn current_layer(&self) -> &str {
+        self.active_layers
+            .last()
+            .or(self.base_layer.as_ref())
+            .map_or(DEFAULT_LAYER, |name| name.as_str())
+    }
+
+    pub fn stick(&self, stick: Stick) -> &StickConfig {
+        match stick {
+            Stick::Left => &self.left_stick,
+            Stick::Right => &self.right_stick,
+        }
+    }
+
+    // the left stick moves the cursor and the right stick scrolls unless set otherwise
+    pub fn stick_role(&self, stick: Stick) -> &StickRole {
+        static CURSOR: StickRole = StickRole::Cursor;
+        static SCROLL: StickRole = StickRole::Scroll;
+        match (&self.stick(stick).role, stick) {
+            (Some(role), _) => role,
+            (None, Stick::Left) => &CURSOR,
+            (None, Stick::Right) => &SCROLL,
+        }
+    }
+
+    pub fn stick_mut(&mut self, stick: Stick) -> &mut StickConfig {
+        match stick {
+            Stick::Left => &mut self.left_stick,
+            Stick::Right => &mut self.right_stick,
+        }
+    }
+
+    pub fn trigger(&self, trigger: Trigger) -> &TriggerConfig {
+        match trigger {
+            Trigger::Left => &self.left_trigger,
+            Trigger::Right => &self.right_trigger,
+        }
+    }
+
     pub fn config_dir(app_handle: &AppHandle) -> PathBuf {
         app_handle
             .path()
@@ -89,118 +323,451 @@ impl Config {
         std::fs::create_dir_all(config_dir)?;
 
         let config_file_path = self.config_file().ok_or(anyhow!("Config file not set"))?;
-        // if let Ok(contents) = std::fs::read_to_string(&config_file_path) {
-        //     if let Ok(diff) = diff(self, &contents) {
-        //         let mut config_file = std::fs::File::create(&config_file_path)?;
-        //         config_file.write_all(diff.as_bytes())?;
-        //         return Ok(());
-        //     } else {
-        //         log::error!("Could not diff config file");
-        //     }
-        // } else {
-        //     log::error!("Could not read config file");
-        // }
-
-        let stringified = toml::to_string(&self)?;
-        let mut config_file = std::fs::File::create(&config_file_path)?;
-        config_file.write_all(stringified.as_bytes())?;
+        let stringified = match std::fs::read_to_string(&config_file_path) {
+            Ok(contents) => {
+                // never write over a file the user still has to fix
+                if let Err(e) = Self::parse(&contents) {
+                    return Err(anyhow!("Not saving over a config file with errors: {}", e));
+                }
+                // rewriting the whole file would lose the user's comments and formatting
+                diff(self, &contents).map_err(|e| anyhow!("Could not update config file: {}", e))?
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => toml::to_string(&self)?,
+            Err(e) => {
+                return Err(anyhow!(
+                    "Not saving over a config file that can't be read: {}",
+                    e
+                ))
+            }
+        };
+        backups::replace(&config_file_path, &stringified)?;
 
         log::info!("Saved config");
         Ok(())
     }
 
+    pub fn backups(&self) -> Result<Vec<Backup>> {
+        let config_file_path = self.config_file().ok_or(anyhow!("Config file not set"))?;
+        backups::list(&config_file_path)
+    }
+
+    // puts a backup back in place, the config it replaces becomes a backup itself
+    pub fn restore_backup(&mut self, name: &str) -> Result<()> {
+        let config_file_path = self.config_file().ok_or(anyhow!("Config file not set"))?;
+        let config_text = backups::read(&config_file_path, name)?;
+        let new = Self::parse(&config_text)?;
+        backups::replace(&config_file_path, &config_text)?;
+        self.reload(new);
+
+        log::info!("Restored config from {}", name);
+        Ok(())
+    }
+
     pub fn load(app_handle: &AppHandle) -> Result<Self> {
         let config_dir_path = Self::config_dir(app_handle);
         log::info!("Loading config from {:?}", config_dir_path);
         std::fs::create_dir_all(&config_dir_path)?;
 
         let config_file_path = Self::with_config_file(&config_dir_path);
-        let config_text = std::fs::read_to_string(config_file_path)?;
-        let mut config: Self = toml::from_str(&config_text)?;
+        let config_text = match std::fs::read_to_string(config_file_path) {
+            Ok(text) => text,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Err(e.into()),
+            Err(e) => return Err(ConfigError::unreadable(&e).into()),
+        };
+        let mut config = Self::parse(&config_text)?;
         config.config_dir = Some(config_dir_path);
 
-        log::info!("Loaded config"); 
+        log::info!("Loaded config");
+        Ok(config)
+    }
+
+    pub fn parse(config_text: &str) -> std::result::Result<Self, ConfigError> {
+        let config: Self = toml::from_str(config_text).map_err(|e| {
+            ConfigError::new(e.message(), config_text, e.span().map(|span| span.start))
+        })?;
+
+        if let Err((key, message)) = config.validate() {
+            // point at the value that's wrong
+            let offset = toml::from_str::<HashMap<String, toml::Spanned<toml::Value>>>(config_text)
+                .ok()
+                .and_then(|values| Some(values.get(key)?.span().start));
+            return Err(ConfigError::new(message, config_text, offset));
+        }
         Ok(config)
     }
+
+    // catches values that parse but make no sense, returning the key and what's wrong with it
+    pub fn validate(&self) -> std::result::Result<(), (&'static str, &'static str)> {
+        if !self.speed.is_finite() {
+            return Err(("speed", "speed must be a number"));
+        }
+        if self.speed < 0.0 {
+            return Err(("speed", "speed can't be negative"));
+        }
+        if !self.scroll_speed.is_finite() {
+            return Err(("scroll_speed", "scroll_speed must be a number"));
+        }
+        if self.tick_rate == 0 {
+            return Err(("tick_rate", "tick_rate must be above 0"));
+        }
+        let curves = [
+            ("left_stick", &self.left_stick.curve),
+            ("right_stick", &self.right_stick.curve),
+            ("left_trigger", &self.left_trigger.curve),
+            ("right_trigger", &self.right_trigger.curve),
+        ];
+        for (key, curve) in curves {
+            curve.validate().map_err(|message| (key, message))?;
+        }
+        for repeat in self.repeat.values() {
+            repeat.validate().map_err(|message| ("repeat", message))?;
+        }
+
+        let layer_exists = |name: &str| name == DEFAULT_LAYER || self.layers.contains_key(name);
+        if !self.base_layer.as_deref().map_or(true, layer_exists) {
+            return Err(("base_layer", "base_layer must name a layer in [layers]"));
+        }
+        for (key, actions) in self.bound_actions() {
+            if !actions.iter().filter_map(Action::layer).all(layer_exists) {
+                return Err((key, "layer actions must name a layer in [layers]"));
+            }
+        }
+        Ok(())
+    }
+
+    // every list of bound actions with the top level key it's under
+    fn bound_actions(&self) -> Vec<(&'static str, &Vec<Action>)> {
+        let mut bound = vec![];
+        bound.extend(self.actions.values().map(|actions| ("actions", actions)));
+        bound.extend(
+            self.layers
+                .values()
+                .flat_map(|layer| layer.values())
+                .map(|actions| ("layers", actions)),
+        );
+        bound.extend(self.chords.iter().map(|chord| ("chords", &chord.actions)));
+        bound.extend(
+            self.gestures
+                .values()
+                .flat_map(|gestures| [&gestures.tap, &gestures.hold, &gestures.double_tap])
+                .map(|actions| ("gestures", actions)),
+        );
+        for (key, stick) in [
+            ("left_stick", &self.left_stick),
+            ("right_stick", &self.right_stick),
+        ] {
+            if let Some(StickRole::Radial(sectors)) = &stick.role {
+                bound.extend(sectors.iter().map(|actions| (key, actions)));
+            }
+        }
+        bound
+    }
+
+    // swaps in a config read from disk, keeping the state that only exists while running
+    pub fn reload(&mut self, mut new: Self) {
+        new.speed_mult = self.speed_mult;
+        new.passthrough = self.passthrough;
+        new.gamepad_id = self.gamepad_id;
+        new.gamepads = std::mem::take(&mut self.gamepads);
+        new.active_layers = std::mem::take(&mut self.active_layers);
+        new.latched = std::mem::take(&mut self.latched);
+        new.config_dir = self.config_dir.take();
+        *self = new;
+    }
 }
 
-// fn diff<T: serde::Serialize>(config: &T, toml_content: &str) -> anyhow::Result<String> {
-//     let mut doc = toml_content.parse::<toml_edit::Document>()?;
-//
-//     let serialized = toml::to_string(config)?;
-//     let new_doc = serialized.parse::<toml_edit::Document>()?;
-//
-//     println!(
-//         "doc:\n{}\n\nnew:\n{}",
-//         &doc.to_string(),
-//         &new_doc.to_string()
-//     );
-//
-//     log::info!("Diffing config");
-//
-//     for (key, new_value) in new_doc.iter() {
-//         log::info!("Checking key: {}", key);
-//         if let Some(old_value) = doc.get(key) {
-//             log::info!("Key exists: {}", key);
-//
-//             if !deep_cmp(old_value, new_value) {
-//                 log::info!("Updating key: {}", key);
-//                 doc[key] = new_value.clone();
-//             }
-//         } else {
-//             // If the key doesn't exist, add it
-//             doc[key] = new_value.clone();
-//         }
-//     }
-//
-//     // Return the new TOML content as a string
-//
-//     Ok(doc.to_string())
-// }
-//
-// fn cmp_value(a: &toml_edit::Value, b: &toml_edit::Value) -> bool {
-//     match (a, b) {
-//         (toml_edit::Value::String(a), toml_edit::Value::String(b)) => a.value() == b.value(),
-//         (toml_edit::Value::Integer(a), toml_edit::Value::Integer(b)) => a.value() == b.value(),
-//         (toml_edit::Value::Float(a), toml_edit::Value::Float(b)) => a.value() == b.value(),
-//         (toml_edit::Value::Boolean(a), toml_edit::Value::Boolean(b)) => a.value() == b.value(),
-//         (toml_edit::Value::Datetime(a), toml_edit::Value::Datetime(b)) => a.value() == b.value(),
-//         (toml_edit::Value::Array(a), toml_edit::Value::Array(b)) => array_cmp(a, b),
-//         (toml_edit::Value::InlineTable(a), toml_edit::Value::InlineTable(b)) => {
-//             inline_table_cmp(a, b)
-//         }
-//         _ => {
-//             false
-//         }
-//     }
-// }
-//
-// fn array_cmp(a: &toml_edit::Array, b: &toml_edit::Array) -> bool {
-//     a.iter().zip(b.iter()).all(|(a, b)| cmp_value(a, b))
-// }
-//
-// fn table_cmp(a: &toml_edit::Table, b: &toml_edit::Table) -> bool {
-//     a.iter()
-//         .all(|(k, v)| b.get(k).map_or(false, |bv| deep_cmp(v, bv)))
-// }
-//
-// fn inline_table_cmp(a: &toml_edit::InlineTable, b: &toml_edit::InlineTable) -> bool {
-//     a.iter()
-//         .all(|(k, v)| b.get(k).map_or(false, |bv| cmp_value(v, bv)))
-// }
-//
-// fn deep_cmp(a: &toml_edit::Item, b: &toml_edit::Item) -> bool {
-//     match (a, b) {
-//         (toml_edit::Item::None, toml_edit::Item::None) => true,
-//         (toml_edit::Item::Value(a), toml_edit::Item::Value(b)) => cmp_value(a, b),
-//         (toml_edit::Item::Table(a), toml_edit::Item::Table(b)) => a
-//             .iter()
-//             .all(|(k, v)| b.get(k).map_or(false, |bv| deep_cmp(v, bv))),
-//         (toml_edit::Item::ArrayOfTables(a), toml_edit::Item::ArrayOfTables(b)) => {
-//             a.iter().zip(b.iter()).all(|(a, b)| table_cmp(a, b))
-//         }
-//         _ => {
-//             false
-//         }
-//     }
-// }
+// writes the config into an existing file, only touching the keys that changed
+// so comments, formatting and keys the config doesn't know about are kept
+fn diff<T>(config: &T, toml_content: &str) -> Result<String>
+where
+    T: serde::Serialize + serde::de::DeserializeOwned,
+{
+    let mut doc = toml_content.parse::<toml_edit::Document>()?;
+    let new_doc = toml_edit::ser::to_document(config)?;
+
+    let mut changed = Vec::new();
+    patch_table(
+        doc.as_table_mut(),
+        new_doc.as_table(),
+        &mut Vec::new(),
+        &mut changed,
+    );
+
+    // added keys that only restate a default are dropped again, and keys missing
+    // from the config are removed if they were set but left alone if serde ignores them
+    let mut parsed = parse_value::<T>(&doc);
+    for (path, added) in changed {
+        let mut without = doc.clone();
+        remove_path(without.as_table_mut(), &path);
+        let without_parsed = parse_value::<T>(&without);
+        if added == (without_parsed == parsed) {
+            doc = without;
+            parsed = without_parsed;
+        }
+    }
+
+    Ok(doc.to_string())
+}
+
+// paths of keys added to `old` (true) or missing from `new` (false) are pushed to `changed`
+fn patch_table(
+    old: &mut dyn toml_edit::TableLike,
+    new: &dyn toml_edit::TableLike,
+    path: &mut Vec<String>,
+    changed: &mut Vec<(Vec<String>, bool)>,
+) {
+    for (key, new_item) in new.iter() {
+        path.push(key.to_string());
+        match old.get_mut(key) {
+            Some(old_item) => patch_item(old_item, new_item, path, changed),
+            None => {
+                old.insert(key, new_item.clone());
+                changed.push((path.clone(), true));
+            }
+        }
+        path.pop();
+    }
+
+    for (key, _) in old.iter() {
+        if !new.contains_key(key) {
+            let mut path = path.clone();
+            path.push(key.to_string());
+            changed.push((path, false));
+        }
+    }
+}
+
+fn patch_item(
+    old: &mut toml_edit::Item,
+    new: &toml_edit::Item,
+    path: &mut Vec<String>,
+    changed: &mut Vec<(Vec<String>, bool)>,
+) {
+    if deep_cmp(old, new) {
+        return;
+    }
+    if let (Some(old), Some(new)) = (old.as_table_like_mut(), new.as_table_like()) {
+        return patch_table(old, new, path, changed);
+    }
+
+    if let (Some(old), Some(new)) = (old.as_value_mut(), new.as_value()) {
+        // keep the comments around the value
+        let decor = old.decor().clone();
+        *old = new.clone();
+        *old.decor_mut() = decor;
+    } else if let Some(position) = old
+        .as_array_of_tables()
+        .and_then(|tables| tables.get(0))
+        .and_then(|table| table.position())
+    {
+        // keep `[[table]]` headers where they were
+        *old = match new.clone().into_array_of_tables() {
+            Ok(mut tables) => {
+                tables
+                    .iter_mut()
+                    .for_each(|table| table.set_position(position));
+                toml_edit::Item::ArrayOfTables(tables)
+            }
+            Err(new) => new,
+        };
+    } else {
+        *old = new.clone();
+    }
+}
+
+// compares what the items mean rather than how they're written
+fn deep_cmp(a: &toml_edit::Item, b: &toml_edit::Item) -> bool {
+    fn value(item: &toml_edit::Item) -> Option<toml::Value> {
+        use serde::de::{Deserialize, IntoDeserializer};
+        let value = item.clone().into_value().ok()?;
+        toml::Value::deserialize(value.into_deserializer()).ok()
+    }
+    match (value(a), value(b)) {
+        (Some(a), Some(b)) => cmp_value(&a, &b),
+        _ => false,
+    }
+}
+
+// floats are compared as the f32s the config holds, so 0.2 matches 0.20000000298023224
+fn cmp_value(a: &toml::Value, b: &toml::Value) -> bool {
+    match (a, b) {
+        (toml::Value::Float(a), toml::Value::Float(b)) => *a as f32 == *b as f32,
+        (toml::Value::Array(a), toml::Value::Array(b)) => {
+            a.len() == b.len() && a.iter().zip(b).all(|(a, b)| cmp_value(a, b))
+        }
+        (toml::Value::Table(a), toml::Value::Table(b)) => {
+            a.len() == b.len()
+                && a.iter()
+                    .all(|(k, v)| b.get(k).map_or(false, |bv| cmp_value(v, bv)))
+        }
+        _ => a == b,
+    }
+}
+
+fn parse_value<T>(doc: &toml_edit::Document) -> Option<toml::Value>
+where
+    T: serde::Serialize + serde::de::DeserializeOwned,
+{
+    let parsed: T = toml::from_str(&doc.to_string()).ok()?;
+    toml::Value::try_from(parsed).ok()
+}
+
+fn remove_path(table: &mut dyn toml_edit::TableLike, path: &[String]) {
+    match path {
+        [key] => {
+            table.remove(key);
+        }
+        [key, rest @ ..] => {
+            if let Some(table) = table.get_mut(key).and_then(|item| item.as_table_like_mut()) {
+                remove_path(table, rest);
+            }
+        }
+        [] => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reload_keeps_runtime_state() {
+        let mut config = Config {
+            speed_mult: 3.0,
+            passthrough: true,
+            gamepad_id: Some(2),
+            active_layers: vec!["nav".to_string()],
+            config_dir: Some(PathBuf::from("/config")),
+            ..Default::default()
+        };
+
+        let new = Config::parse("speed = 120.0\nspeed_up = 2.0").unwrap();
+        config.reload(new);
+
+        assert_eq!(config.speed, 120.0);
+        assert_eq!(config.speed_up, 2.0);
+        assert_eq!(config.speed_mult, 3.0);
+        assert!(config.passthrough);
+        assert_eq!(config.gamepad_id, Some(2));
+        assert_eq!(config.active_layers, vec!["nav".to_string()]);
+        assert_eq!(
+            config.config_file(),
+            Some(PathBuf::from("/config/config.toml"))
+        );
+    }
+
+    #[test]
+    fn save_keeps_comments_and_unknown_keys() {
+        let contents = "# my settings\n\
+            speed = 70.0 # fast enough\n\
+            unknown = \"kept\"\n\
+            \n\
+            [left_stick]\n\
+            # my stick drifts\n\
+            inner_deadzone = 0.2\n";
+        let mut config = Config::parse(contents).unwrap();
+        config.speed = 90.0;
+        config.left_stick.outer_deadzone = 0.25;
+
+        let saved = diff(&config, contents).unwrap();
+        assert!(saved.starts_with("# my settings\nspeed = 90.0 # fast enough\n"));
+        assert!(saved.contains("unknown = \"kept\""));
+        assert!(saved.contains("# my stick drifts\ninner_deadzone = 0.2\n"));
+        assert!(saved.contains("outer_deadzone = 0.25"));
+
+        let reloaded = Config::parse(&saved).unwrap();
+        assert_eq!(reloaded.speed, 90.0);
+        assert_eq!(reloaded.left_stick.outer_deadzone, 0.25);
+    }
+
+    #[test]
+    fn save_leaves_unchanged_file_alone() {
+        let contents = "speed = 70.0\n\
+            \n\
+            # bindings\n\
+            [actions]\n\
+            South = [\"rumble\"]\n";
+        let config = Config::parse(contents).unwrap();
+        assert_eq!(diff(&config, contents).unwrap(), contents);
+    }
+
+    #[test]
+    fn save_removes_unset_values() {
+        let contents = "gamepad_uuid = \"1234\"\n\
+            \n\
+            [actions]\n\
+            South = [\"rumble\"]\n\
+            East = [\"toggle_vis\"]\n";
+        let mut config = Config::parse(contents).unwrap();
+        config.gamepad_uuid = None;
+        config.actions = toml::from_str("South = [\"rumble\"]").unwrap();
+
+        let saved = diff(&config, contents).unwrap();
+        assert_eq!(saved, "\n[actions]\nSouth = [\"rumble\"]\n");
+    }
+
+    #[test]
+    fn parse_rejects_invalid_values() {
+        assert!(Config::parse("speed = -5.0").is_err());
+        assert!(Config::parse("tick_rate = 0").is_err());
+        assert!(Config::parse("speed = \"fast\"").is_err());
+        assert!(Config::parse("[left_stick.curve]\npower = 0.0").is_err());
+        assert!(Config::parse("base_layer = \"nav\"").is_err());
+        assert!(Config::parse("[actions]\nSouth = [{ toggle_layer = \"nav\" }]").is_err());
+        assert!(Config::parse(
+            "[[chords]]\nbuttons = [\"North\"]\nactions = [{ layer = \"nav\" }]"
+        )
+        .is_err());
+
+        let config = Config::parse(
+            "base_layer = \"nav\"\n[actions]\nSouth = [{ toggle_layer = \"nav\" }]\n[layers.nav]\n",
+        )
+        .unwrap();
+        assert_eq!(config.base_layer.as_deref(), Some("nav"));
+        assert!(Config::parse("[right_trigger.curve]\ncustom = [[0.8, 0.5], [0.2, 0.1]]").is_err());
+        assert!(Config::parse("[repeat.South]\nrate = 0.0").is_err());
+        assert!(Config::parse("[repeat.South]\nrate = inf").is_err());
+        assert!(Config::parse("[repeat.South]\nrate = 1e9").is_err());
+        assert!(Config::parse("[repeat.South]\nrate = 30.0").is_ok());
+    }
+
+    #[test]
+    fn parse_errors_point_at_the_problem() {
+        let error = Config::parse("speed_up = 2.0\nspeed = -5.0\n").unwrap_err();
+        assert_eq!(error.message, "speed can't be negative");
+        assert_eq!((error.line, error.column), (Some(2), Some(9)));
+
+        let error = Config::parse("[actions]\nSouth = [\"clik\"]\n").unwrap_err();
+        assert!(error.message.starts_with("unknown variant `clik`"));
+        assert_eq!((error.line, error.column), (Some(2), Some(10)));
+
+        let error = Config::parse("speed = \"fast\"").unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "invalid type: string \"fast\", expected f32 at line 1, column 9"
+        );
+    }
+
+    #[test]
+    fn save_refuses_to_overwrite_errors() {
+        let dir = std::env::temp_dir().join(format!("xouse-config-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = Config::with_config_file(&dir);
+        std::fs::write(&path, "speed = -5.0\n").unwrap();
+
+        let config = Config {
+            config_dir: Some(dir.clone()),
+            ..Default::default()
+        };
+        assert!(config.save().is_err());
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "speed = -5.0\n");
+        assert!(config.backups().unwrap().is_empty());
+
+        // latin-1 text isn't utf-8 so the file can't be read, but it's still the user's
+        std::fs::write(&path, b"text = \"caf\xe9\"\n").unwrap();
+        assert!(config.save().is_err());
+        assert_eq!(std::fs::read(&path).unwrap(), b"text = \"caf\xe9\"\n");
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,205 @@
+use gilrs::{ev::Axis, Button, Event, EventType, Gilrs};
+use std::time::{Duration, Instant};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum InputEventType {
+    ButtonPressed(Button),
+    ButtonReleased(Button),
+    ButtonChanged(Button, f32), // analog value in [0, 1]
+    AxisChanged(Axis, f32),
+    Connected(GamepadInfo),
+    Disconnected,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct InputEvent {
+    pub id: usize, // gamepad id
+    pub event: InputEventType,
+}
+
+#[derive(Debug, Clone, PartialEq, serde::Serialize)]
+pub struct GamepadInfo {
+    pub id: usize,
+    pub name: String,
+    pub uuid: String, // stable across reconnects and restarts
+    pub ff_supported: bool,
+}
+
+// something the perform loop can read gamepad events from
+pub trait InputSource {
+    // returns the next pending event without blocking
+    fn next_event(&mut self) -> Option<InputEvent>;
+    // blocks until an event is pending or the timeout runs out
+    fn wait(&mut self, timeout: Duration);
+    // all currently connected gamepads
+    fn gamepads(&self) -> Vec<GamepadInfo>;
+}
+
+pub fn format_uuid(uuid: [u8; 16]) -> String {
+    let hex = uuid
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect::<String>();
+    format!(
+        "{}-{}-{}-{}-{}",
+        &hex[0..8],
+        &hex[8..12],
+        &hex[12..16],
+        &hex[16..20],
+        &hex[20..32]
+    )
+}
+
+pub struct GilrsInput {
+    gilrs: Gilrs,
+    pending: Option<InputEvent>, // received while waiting
+}
+
+impl GilrsInput {
+    pub fn new(gilrs: Gilrs) -> Self {
+        Self {
+            gilrs,
+            pending: None,
+        }
+    }
+
+    fn convert(&self, event: Event) -> Option<InputEvent> {
+        let Event { id, event, .. } = event;
+        let event = match event {
+            EventType::ButtonPressed(button, _) => InputEventType::ButtonPressed(button),
+            EventType::ButtonReleased(button, _) => InputEventType::ButtonReleased(button),
+            EventType::ButtonChanged(button, value, _) => {
+                InputEventType::ButtonChanged(button, value)
+            }
+            EventType::AxisChanged(axis, value, _) => InputEventType::AxisChanged(axis, value),
+            EventType::Connected => InputEventType::Connected(self.gamepad_info(id)),
+            EventType::Disconnected => InputEventType::Disconnected,
+            // events we don't care about
+            _ => return None,
+        };
+        Some(InputEvent {
+            id: id.into(),
+            event,
+        })
+    }
+
+    fn gamepad_info(&self, id: gilrs::GamepadId) -> GamepadInfo {
+        let gamepad = self.gilrs.gamepad(id);
+        GamepadInfo {
+            id: id.into(),
+            name: gamepad.name().to_string(),
+            uuid: format_uuid(gamepad.uuid()),
+            ff_supported: gamepad.is_ff_supported(),
+        }
+    }
+}
+
+impl InputSource for GilrsInput {
+    fn next_event(&mut self) -> Option<InputEvent> {
+        if let Some(event) = self.pending.take() {
+            return Some(event);
+        }
+        while let Some(event) = self.gilrs.next_event() {
+            if let Some(event) = self.convert(event) {
+                return Some(event);
+            }
+        }
+        None
+    }
+
+    fn wait(&mut self, timeout: Duration) {
+        if self.pending.is_some() {
+            return;
+        }
+        let deadline = Instant::now() + timeout;
+        while let Some(event) = self
+            .gilrs
+            .next_event_blocking(Some(deadline.saturating_duration_since(Instant::now())))
+        {
+            if let Some(event) = self.convert(event) {
+                self.pending = Some(event);
+                return;
+            }
+        }
+    }
+
+    fn gamepads(&self) -> Vec<GamepadInfo> {
+        self.gilrs
+            .gamepads()
+            .map(|(id, _)| self.gamepad_info(id))
+            .collect()
+    }
+}
+
+// replays a fixed list of events, used to drive the perform loop in tests
+#[cfg(test)]
+#[derive(Debug, Default)]
+pub struct ScriptedInput {
+    events: std::collections::VecDeque<InputEvent>,
+    gamepads: Vec<GamepadInfo>,
+}
+
+#[cfg(test)]
+impl ScriptedInput {
+    pub fn new(ids: Vec<usize>) -> Self {
+        Self {
+            events: Default::default(),
+            gamepads: ids.into_iter().map(Self::gamepad_info).collect(),
+        }
+    }
+
+    fn gamepad_info(id: usize) -> GamepadInfo {
+        GamepadInfo {
+            id,
+            name: format!("Scripted gamepad {}", id),
+            uuid: format_uuid([id as u8; 16]),
+            ff_supported: false,
+        }
+    }
+
+    pub fn connect(&mut self, id: usize) -> &mut Self {
+        let gamepad = Self::gamepad_info(id);
+        self.gamepads.push(gamepad.clone());
+        self.push(id, InputEventType::Connected(gamepad))
+    }
+
+    pub fn disconnect(&mut self, id: usize) -> &mut Self {
+        self.gamepads.retain(|g| g.id != id);
+        self.push(id, InputEventType::Disconnected)
+    }
+
+    pub fn push(&mut self, id: usize, event: InputEventType) -> &mut Self {
+        self.events.push_back(InputEvent { id, event });
+        self
+    }
+
+    pub fn press(&mut self, id: usize, button: Button) -> &mut Self {
+        self.push(id, InputEventType::ButtonPressed(button))
+    }
+
+    pub fn release(&mut self, id: usize, button: Button) -> &mut Self {
+        self.push(id, InputEventType::ButtonReleased(button))
+    }
+
+    pub fn button_value(&mut self, id: usize, button: Button, value: f32) -> &mut Self {
+        self.push(id, InputEventType::ButtonChanged(button, value))
+    }
+
+    pub fn axis(&mut self, id: usize, axis: Axis, value: f32) -> &mut Self {
+        self.push(id, InputEventType::AxisChanged(axis, value))
+    }
+}
+
+#[cfg(test)]
+impl InputSource for ScriptedInput {
+    fn next_event(&mut self) -> Option<InputEvent> {
+        self.events.pop_front()
+    }
+
+    // the events are all there already
+    fn wait(&mut self, _timeout: Duration) {}
+
+    fn gamepads(&self) -> Vec<GamepadInfo> {
+        self.gamepads.clone()
+    }
+}
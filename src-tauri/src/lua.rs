@@ -6,6 +6,8 @@ use tauri::WebviewWindow;
 use crate::{
     actions::{Action, ActionFn, ActionInterface, Rumble},
     config::Config,
+    macros::MacroRunner,
+    output::OutputSink,
 };
 
 pub fn init_lua<R>(ctx: LuaInterface<R>) -> anyhow::Result<mlua::Lua>
@@ -84,7 +86,6 @@ where
     })?;
     lua.globals().set("rumble", rumble)?;
 
-
     Ok(lua)
 }
 
@@ -96,6 +97,8 @@ where
     pub config: Arc<Mutex<Config>>,
     pub window: WebviewWindow,
     pub rumble: Option<Rumble<R>>,
+    pub output: Arc<dyn OutputSink>,
+    pub macros: MacroRunner,
 }
 
 impl<R> From<LuaInterface<R>> for ActionInterface<'_, R>
@@ -105,9 +108,12 @@ where
     fn from(val: LuaInterface<R>) -> Self {
         ActionInterface {
             config: val.config,
-            window: val.window,
+            window: Some(val.window),
             rumble: val.rumble,
             lua: None,
+            output: val.output,
+            macros: val.macros,
+            binding: "lua".to_string(),
         }
     }
 }
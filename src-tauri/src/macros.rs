@@ -0,0 +1,162 @@
+use crate::actions::MouseButton;
+use crate::output::{OutputError, OutputSink};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+#[derive(Clone, Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+pub enum MacroStep {
+    #[serde(rename = "key_down")]
+    KeyDown(rdev::Key),
+    #[serde(rename = "key_up")]
+    KeyUp(rdev::Key),
+    #[serde(rename = "key_tap")]
+    KeyTap(rdev::Key),
+    #[serde(rename = "click")]
+    Click(MouseButton),
+    #[serde(rename = "move")]
+    Move(i32, i32),
+    #[serde(rename = "scroll")]
+    Scroll(i32, i32),
+    // in milliseconds
+    #[serde(rename = "wait")]
+    Wait(u64),
+    // limited to a US keyboard's characters outside windows, like type_text
+    #[serde(rename = "type")]
+    Type(String),
+}
+
+// how often a waiting macro checks if it was cancelled
+const CANCEL_POLL_MS: u64 = 10;
+
+struct RunningMacro {
+    binding: String, // what started it
+    cancel: Arc<AtomicBool>,
+}
+
+// runs macros on their own threads so they don't hold up the perform loop
+#[derive(Clone, Default)]
+pub struct MacroRunner {
+    running: Arc<Mutex<Vec<RunningMacro>>>,
+}
+
+impl MacroRunner {
+    // starts the macro, or cancels it if the binding already started one that's still running
+    pub fn toggle(&self, binding: &str, steps: &[MacroStep], output: Arc<dyn OutputSink>) {
+        let mut running = self.running.lock().unwrap();
+        let already_running = running
+            .iter()
+            .find(|m| m.binding == binding && !m.cancel.load(Ordering::Relaxed));
+        if let Some(m) = already_running {
+            // the thread removes it once it has stopped
+            log::info!(target: "actions", "cancelling macro");
+            m.cancel.store(true, Ordering::Relaxed);
+            return;
+        }
+
+        let cancel = Arc::new(AtomicBool::new(false));
+        running.push(RunningMacro {
+            binding: binding.to_string(),
+            cancel: cancel.clone(),
+        });
+        drop(running);
+
+        let steps = steps.to_vec();
+        let registry = self.running.clone();
+        thread::spawn(move || {
+            if let Err(e) = run_steps(&steps, output.as_ref(), &cancel) {
+                log::error!("Macro error: {:?}", e);
+            }
+            registry
+                .lock()
+                .unwrap()
+                .retain(|m| !Arc::ptr_eq(&m.cancel, &cancel));
+        });
+    }
+
+    // stops every running macro, each thread lets go of its keys as it stops
+    pub fn cancel_all(&self) {
+        for m in self.running.lock().unwrap().iter() {
+            m.cancel.store(true, Ordering::Relaxed);
+        }
+    }
+
+    #[cfg(test)]
+    pub fn wait_idle(&self) {
+        while !self.running.lock().unwrap().is_empty() {
+            thread::sleep(Duration::from_millis(1));
+        }
+    }
+}
+
+fn run_steps(
+    steps: &[MacroStep],
+    output: &dyn OutputSink,
+    cancel: &AtomicBool,
+) -> Result<(), OutputError> {
+    // keys left down by the macro, released however it stops
+    let mut down = vec![];
+    let mut result = run_until_done(steps, output, cancel, &mut down);
+
+    // keep the first error but still let go of every key
+    for key in down.into_iter().rev() {
+        result = result.and(output.key_up(key));
+    }
+    result
+}
+
+fn run_until_done(
+    steps: &[MacroStep],
+    output: &dyn OutputSink,
+    cancel: &AtomicBool,
+    down: &mut Vec<rdev::Key>,
+) -> Result<(), OutputError> {
+    for step in steps {
+        if cancel.load(Ordering::Relaxed) {
+            break;
+        }
+        match step {
+            MacroStep::KeyDown(key) => {
+                output.key_down(*key)?;
+                down.push(*key);
+            }
+            MacroStep::KeyUp(key) => {
+                output.key_up(*key)?;
+                down.retain(|k| k != key);
+            }
+            MacroStep::KeyTap(key) => {
+                output.key_down(*key)?;
+                output.key_up(*key)?;
+            }
+            MacroStep::Click(button) => {
+                output.press_button(button)?;
+                output.release_button(button)?;
+            }
+            MacroStep::Move(dx, dy) => output.move_relative(*dx, *dy)?,
+            MacroStep::Scroll(dx, dy) => output.scroll(*dx, *dy)?,
+            MacroStep::Wait(ms) => wait(Duration::from_millis(*ms), cancel),
+            MacroStep::Type(text) => {
+                for c in text.chars() {
+                    if cancel.load(Ordering::Relaxed) {
+                        break;
+                    }
+                    crate::text::type_char(output, c)?;
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+// sleeps for the duration, waking up early if cancelled
+fn wait(duration: Duration, cancel: &AtomicBool) {
+    let end = Instant::now() + duration;
+    loop {
+        let now = Instant::now();
+        if now >= end || cancel.load(Ordering::Relaxed) {
+            return;
+        }
+        thread::sleep((end - now).min(Duration::from_millis(CANCEL_POLL_MS)));
+    }
+}
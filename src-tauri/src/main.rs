@@ -2,13 +2,28 @@
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
 
 mod actions;
+mod backups;
+mod buttons;
 mod config;
+mod input;
 mod lua;
+mod macros;
+mod output;
 mod perform;
+mod roles;
 mod setup;
+mod stick;
+mod text;
+mod triggers;
+mod watch;
 
-use config::Config;
+use actions::Action;
+use backups::Backup;
+use config::{Config, ConfigError};
+use input::GamepadInfo;
 use std::sync::{Arc, Mutex};
+use stick::{Stick, StickConfig};
+use tauri::Emitter;
 
 struct AppState {
     config: Arc<Mutex<Config>>,
@@ -33,7 +48,21 @@ fn main() {
                 .build(),
         )
         .invoke_handler(tauri::generate_handler![
-            save_config, get_speed, set_speed, get_config, timing
+            save_config,
+            get_speed,
+            set_speed,
+            get_stick,
+            set_stick,
+            list_gamepads,
+            get_gamepad,
+            select_gamepad,
+            get_layer,
+            get_latched,
+            get_config,
+            get_config_error,
+            list_backups,
+            restore_backup,
+            timing
         ])
         .manage(AppState {
             config: Arc::clone(&config_mtx),
@@ -69,6 +98,64 @@ fn set_speed(state: tauri::State<AppState>, speed: f32) -> Result<(), String> {
     Ok(())
 }
 
+#[tauri::command]
+fn get_stick(state: tauri::State<AppState>, stick: Stick) -> Result<StickConfig, String> {
+    let config = state.config.lock().map_err(|e| e.to_string())?;
+    Ok(config.stick(stick).clone())
+}
+
+#[tauri::command]
+fn set_stick(
+    state: tauri::State<AppState>,
+    stick: Stick,
+    settings: StickConfig,
+) -> Result<(), String> {
+    settings.curve.validate()?;
+    let mut config = state.config.lock().map_err(|e| e.to_string())?;
+    *config.stick_mut(stick) = settings;
+    Ok(())
+}
+
+#[tauri::command]
+fn list_gamepads(state: tauri::State<AppState>) -> Result<Vec<GamepadInfo>, String> {
+    let config = state.config.lock().map_err(|e| e.to_string())?;
+    Ok(config.gamepads.clone())
+}
+
+#[tauri::command]
+fn get_gamepad(state: tauri::State<AppState>) -> Result<Option<usize>, String> {
+    let config = state.config.lock().map_err(|e| e.to_string())?;
+    Ok(config.gamepad_id)
+}
+
+#[tauri::command]
+fn select_gamepad(state: tauri::State<AppState>, id: usize) -> Result<(), String> {
+    let mut config = state.config.lock().map_err(|e| e.to_string())?;
+    let uuid = config
+        .gamepads
+        .iter()
+        .find(|gamepad| gamepad.id == id)
+        .ok_or(format!("No gamepad with id {}", id))?
+        .uuid
+        .clone();
+    config.gamepad_id = Some(id);
+    // remembered for the next launch once the config is saved
+    config.gamepad_uuid = Some(uuid);
+    Ok(())
+}
+
+#[tauri::command]
+fn get_layer(state: tauri::State<AppState>) -> Result<String, String> {
+    let config = state.config.lock().map_err(|e| e.to_string())?;
+    Ok(config.current_layer().to_string())
+}
+
+#[tauri::command]
+fn get_latched(state: tauri::State<AppState>) -> Result<Vec<Action>, String> {
+    let config = state.config.lock().map_err(|e| e.to_string())?;
+    Ok(config.latched.clone())
+}
+
 #[tauri::command]
 fn get_config(state: tauri::State<AppState>) -> Result<Config, String> {
     let config = state.config.lock().map_err(|e| e.to_string())?;
@@ -76,6 +163,32 @@ fn get_config(state: tauri::State<AppState>) -> Result<Config, String> {
     Ok(config.clone())
 }
 
+#[tauri::command]
+fn get_config_error(state: tauri::State<AppState>) -> Result<Option<ConfigError>, String> {
+    let config = state.config.lock().map_err(|e| e.to_string())?;
+    Ok(config.load_error.clone())
+}
+
+#[tauri::command]
+fn list_backups(state: tauri::State<AppState>) -> Result<Vec<Backup>, String> {
+    let config = state.config.lock().map_err(|e| e.to_string())?;
+    config.backups().map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn restore_backup(
+    app_handle: tauri::AppHandle,
+    state: tauri::State<AppState>,
+    name: String,
+) -> Result<(), String> {
+    let mut config = state.config.lock().map_err(|e| e.to_string())?;
+    config.restore_backup(&name).map_err(|e| e.to_string())?;
+    app_handle
+        .emit("config_reloaded", &*config)
+        .map_err(|e| e.to_string())?;
+    Ok(())
+}
+
 #[tauri::command]
 fn timing(time_in: String) -> Result<(String, String), String> {
     let time = std::time::SystemTime::now()
@@ -0,0 +1,273 @@
+use crate::actions::MouseButton;
+use mouce::MouseActions;
+use std::sync::Mutex;
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum OutputError {
+    #[error("Mouce error: {0}")]
+    Mouce(#[from] mouce::error::Error),
+
+    #[error("Simulate error: {0}")]
+    Simulate(#[from] rdev::SimulateError),
+
+    #[error("Can't type {0:?}, only characters on a US keyboard can be typed on this platform")]
+    Unsupported(char),
+
+    #[cfg(windows)]
+    #[error("SendInput error: {0}")]
+    SendInput(#[from] windows::core::Error),
+}
+
+// somewhere mouse and keyboard events get injected
+pub trait OutputSink: Send + Sync {
+    fn move_relative(&self, dx: i32, dy: i32) -> Result<(), OutputError>;
+    // in wheel notches, positive y scrolls up and positive x scrolls right
+    fn scroll(&self, dx: i32, dy: i32) -> Result<(), OutputError>;
+    // how many steps a notch can be split into, 1 if only whole notches can be sent
+    fn scroll_resolution(&self) -> i32 {
+        1
+    }
+    // in steps of 1 / scroll_resolution notches
+    fn scroll_fine(&self, dx: i32, dy: i32) -> Result<(), OutputError> {
+        self.scroll(dx, dy)
+    }
+    fn press_button(&self, button: &MouseButton) -> Result<(), OutputError>;
+    fn release_button(&self, button: &MouseButton) -> Result<(), OutputError>;
+    fn key_down(&self, key: rdev::Key) -> Result<(), OutputError>;
+    fn key_up(&self, key: rdev::Key) -> Result<(), OutputError>;
+    // types a character directly, regardless of keyboard layout
+    fn type_unicode(&self, c: char) -> Result<(), OutputError> {
+        Err(OutputError::Unsupported(c))
+    }
+    // whether type_unicode works, so text can be checked before any of it is typed
+    fn types_unicode(&self) -> bool {
+        false
+    }
+}
+
+// injects events into the os using mouce and rdev
+pub struct SystemOutput {
+    mouse: Mutex<mouce::Mouse>,
+}
+
+impl SystemOutput {
+    pub fn new() -> Self {
+        Self {
+            mouse: Mutex::new(mouce::Mouse::new()),
+        }
+    }
+}
+
+impl OutputSink for SystemOutput {
+    fn move_relative(&self, dx: i32, dy: i32) -> Result<(), OutputError> {
+        self.mouse.lock().unwrap().move_relative(dx, dy)?;
+        Ok(())
+    }
+
+    fn scroll(&self, dx: i32, dy: i32) -> Result<(), OutputError> {
+        use mouce::common::ScrollDirection;
+
+        let mouse = self.mouse.lock().unwrap();
+        let y_direction = if dy > 0 {
+            ScrollDirection::Up
+        } else {
+            ScrollDirection::Down
+        };
+        for _ in 0..dy.abs() {
+            mouse.scroll_wheel(&y_direction)?;
+        }
+        let x_direction = if dx > 0 {
+            ScrollDirection::Right
+        } else {
+            ScrollDirection::Left
+        };
+        for _ in 0..dx.abs() {
+            mouse.scroll_wheel(&x_direction)?;
+        }
+        Ok(())
+    }
+
+    #[cfg(windows)]
+    fn scroll_resolution(&self) -> i32 {
+        use windows::Win32::UI::WindowsAndMessaging::WHEEL_DELTA;
+        WHEEL_DELTA as i32
+    }
+
+    #[cfg(windows)]
+    fn scroll_fine(&self, dx: i32, dy: i32) -> Result<(), OutputError> {
+        use windows::Win32::UI::Input::KeyboardAndMouse as kbm;
+
+        unsafe {
+            if dy != 0 {
+                kbm::mouse_event(kbm::MOUSEEVENTF_WHEEL, 0, 0, dy, 0);
+            }
+            if dx != 0 {
+                kbm::mouse_event(kbm::MOUSEEVENTF_HWHEEL, 0, 0, dx, 0);
+            }
+        }
+        Ok(())
+    }
+
+    fn press_button(&self, button: &MouseButton) -> Result<(), OutputError> {
+        self.mouse.lock().unwrap().press_button(&button.into())?;
+        Ok(())
+    }
+
+    fn release_button(&self, button: &MouseButton) -> Result<(), OutputError> {
+        self.mouse.lock().unwrap().release_button(&button.into())?;
+        Ok(())
+    }
+
+    fn key_down(&self, key: rdev::Key) -> Result<(), OutputError> {
+        rdev::simulate(&rdev::EventType::KeyPress(key))?;
+        Ok(())
+    }
+
+    fn key_up(&self, key: rdev::Key) -> Result<(), OutputError> {
+        rdev::simulate(&rdev::EventType::KeyRelease(key))?;
+        Ok(())
+    }
+
+    #[cfg(windows)]
+    fn types_unicode(&self) -> bool {
+        true
+    }
+
+    #[cfg(windows)]
+    fn type_unicode(&self, c: char) -> Result<(), OutputError> {
+        use windows::Win32::UI::Input::KeyboardAndMouse as kbm;
+
+        // characters outside the BMP are sent as a surrogate pair
+        let mut units = [0u16; 2];
+        let mut inputs = vec![];
+        for unit in c.encode_utf16(&mut units).iter() {
+            for flags in [
+                kbm::KEYEVENTF_UNICODE,
+                kbm::KEYEVENTF_UNICODE | kbm::KEYEVENTF_KEYUP,
+            ] {
+                inputs.push(kbm::INPUT {
+                    r#type: kbm::INPUT_KEYBOARD,
+                    Anonymous: kbm::INPUT_0 {
+                        ki: kbm::KEYBDINPUT {
+                            wVk: kbm::VIRTUAL_KEY(0),
+                            wScan: *unit,
+                            dwFlags: flags,
+                            time: 0,
+                            dwExtraInfo: 0,
+                        },
+                    },
+                });
+            }
+        }
+
+        let sent = unsafe { kbm::SendInput(&inputs, std::mem::size_of::<kbm::INPUT>() as i32) };
+        if sent as usize != inputs.len() {
+            return Err(windows::core::Error::from_win32().into());
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+#[derive(Debug, Clone, PartialEq)]
+pub enum OutputEvent {
+    Move(i32, i32),
+    Scroll(i32, i32),
+    FineScroll(i32, i32),
+    ButtonDown(MouseButton),
+    ButtonUp(MouseButton),
+    KeyDown(rdev::Key),
+    KeyUp(rdev::Key),
+    Unicode(char),
+}
+
+// records every event instead of injecting it, used to make assertions in tests
+#[cfg(test)]
+#[derive(Debug, Default)]
+pub struct RecordingOutput {
+    events: Mutex<Vec<OutputEvent>>,
+    unicode: bool,     // whether type_unicode is supported
+    fine_scroll: bool, // whether scroll_fine sends 120 steps per notch
+}
+
+#[cfg(test)]
+impl RecordingOutput {
+    pub fn with_unicode() -> Self {
+        Self {
+            unicode: true,
+            ..Default::default()
+        }
+    }
+
+    pub fn with_fine_scroll() -> Self {
+        Self {
+            fine_scroll: true,
+            ..Default::default()
+        }
+    }
+
+    // returns the recorded events and clears the recording
+    pub fn take(&self) -> Vec<OutputEvent> {
+        std::mem::take(&mut *self.events.lock().unwrap())
+    }
+
+    fn record(&self, event: OutputEvent) -> Result<(), OutputError> {
+        self.events.lock().unwrap().push(event);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+impl OutputSink for RecordingOutput {
+    fn move_relative(&self, dx: i32, dy: i32) -> Result<(), OutputError> {
+        self.record(OutputEvent::Move(dx, dy))
+    }
+
+    fn scroll(&self, dx: i32, dy: i32) -> Result<(), OutputError> {
+        self.record(OutputEvent::Scroll(dx, dy))
+    }
+
+    fn scroll_resolution(&self) -> i32 {
+        if self.fine_scroll {
+            120
+        } else {
+            1
+        }
+    }
+
+    fn scroll_fine(&self, dx: i32, dy: i32) -> Result<(), OutputError> {
+        if self.fine_scroll {
+            self.record(OutputEvent::FineScroll(dx, dy))
+        } else {
+            self.scroll(dx, dy)
+        }
+    }
+
+    fn press_button(&self, button: &MouseButton) -> Result<(), OutputError> {
+        self.record(OutputEvent::ButtonDown(button.clone()))
+    }
+
+    fn release_button(&self, button: &MouseButton) -> Result<(), OutputError> {
+        self.record(OutputEvent::ButtonUp(button.clone()))
+    }
+
+    fn key_down(&self, key: rdev::Key) -> Result<(), OutputError> {
+        self.record(OutputEvent::KeyDown(key))
+    }
+
+    fn key_up(&self, key: rdev::Key) -> Result<(), OutputError> {
+        self.record(OutputEvent::KeyUp(key))
+    }
+
+    fn type_unicode(&self, c: char) -> Result<(), OutputError> {
+        if !self.unicode {
+            return Err(OutputError::Unsupported(c));
+        }
+        self.record(OutputEvent::Unicode(c))
+    }
+
+    fn types_unicode(&self) -> bool {
+        self.unicode
+    }
+}
@@ -1,36 +1,42 @@
 use anyhow::Result;
-use gilrs::{ev::Axis, ff, Event, EventType, Gilrs};
-use mouce::{self, Mouse, MouseActions};
-use std::mem::drop;
+use gilrs::{ev::Axis, ff, Gilrs};
 use std::sync::{Arc, Mutex};
-use std::thread;
-use std::time::Duration;
+use std::time::{Duration, Instant};
+use tauri::Emitter;
 
-use crate::actions::{ActionFn, ActionInterface, Rumble};
+use crate::actions::{Action, ActionFn, ActionInterface, Rumble};
+use crate::buttons::{ButtonTracker, Fired};
 use crate::config::Config;
+use crate::input::{GamepadInfo, GilrsInput, InputEvent, InputEventType, InputSource};
+use crate::macros::MacroRunner;
+use crate::output::{OutputSink, SystemOutput};
+use crate::roles::{StickRole, StickState};
+use crate::stick::{Stick, Vec2};
+use crate::triggers::Trigger;
 
-fn ease(x: f32) -> f32 {
-    x
+#[derive(Clone, serde::Serialize)]
+struct GamepadEvent {
+    id: usize,
+    name: String,
 }
 
-#[derive(Debug, Clone, Copy, Default)]
-struct Vec2<T> {
-    pub x: T,
-    pub y: T,
+#[derive(Clone, serde::Serialize)]
+struct RadialEvent {
+    stick: Stick,
+    sector: Option<usize>, // none when nothing is selected
 }
 
-const POLL_TIME_MS: u64 = 1;
+// longest time a single tick moves for, so a stall doesn't make the cursor jump,
+// unless the tick rate is so low that ticks are longer than this
+const MAX_TICK_TIME_MS: u64 = 50;
+// how long to wait for an event when nothing is moving, so config changes are picked up
+const IDLE_WAIT_MS: u64 = 1000;
+const NO_GAMEPAD_WAIT_MS: u64 = 2000;
 const UNIT_MULTIPLIER: f32 = 0.02;
 
 pub fn start(window: tauri::WebviewWindow, config_mx: Arc<Mutex<Config>>) -> Result<()> {
     let mut gilrs = Gilrs::new().unwrap();
 
-    // print all connected gamepads
-    log::info!(
-        "Connected gamepads: {:?}",
-        gilrs.gamepads().map(|(id, _)| id).collect::<Vec<_>>()
-    );
-
     let support_ff = gilrs
         .gamepads()
         .filter_map(|(id, gp)| if gp.is_ff_supported() { Some(id) } else { None })
@@ -61,176 +67,545 @@ pub fn start(window: tauri::WebviewWindow, config_mx: Arc<Mutex<Config>>) -> Res
         effect.play()
     });
 
-    let mut l_stick = Vec2::<f32> { x: 0.0, y: 0.0 };
-    let mut r_stick = Vec2::<f32> { x: 0.0, y: 0.0 };
-
-    let mut remainder = Vec2::<f32> { x: 0.0, y: 0.0 };
-
-    let config = config_mx.lock().unwrap();
-    // window.emit("speed_change", config.speed)?;
-    drop(config);
+    let output: Arc<dyn OutputSink> = Arc::new(SystemOutput::new());
+    // shared so lua and the bindings can cancel each other's macros
+    let macros = MacroRunner::default();
 
     let lua_interface = crate::lua::LuaInterface {
         config: config_mx.clone(),
         window: window.clone(),
         rumble: Some(rumble.clone()),
+        output: output.clone(),
+        macros: macros.clone(),
     };
     let lua_ctx = crate::lua::init_lua(lua_interface).unwrap();
 
-    let mouse = Mouse::new();
+    let mut input = GilrsInput::new(gilrs);
+    let mut performer = Performer::new(
+        config_mx,
+        output,
+        Some(window),
+        Some(rumble),
+        Some(&lua_ctx),
+        macros,
+    );
+    performer.run(&mut input)
+}
+
+// turns gamepad input into mouse movement and actions
+//
+// kept separate from gilrs so it can be driven by any `InputSource`
+pub struct Performer<'lua, R>
+where
+    R: Fn() -> Result<(), gilrs::ff::Error> + Send + Sync + Clone + 'static,
+{
+    config_mx: Arc<Mutex<Config>>,
+    output: Arc<dyn OutputSink>,
+    window: Option<tauri::WebviewWindow>,
+    rumble: Option<Rumble<R>>,
+    lua: Option<&'lua mlua::Lua>,
+    macros: MacroRunner,
+
+    l_stick: Vec2<f32>,
+    r_stick: Vec2<f32>,
+    remainder: Vec2<f32>,
+    l_state: StickState,
+    r_state: StickState,
+    l_trigger: f32,
+    r_trigger: f32,
+    scroll_remainder: Vec2<f32>, // in scroll steps
+    last_tick: Option<Instant>,  // last movement tick, none while nothing is moving
+
+    buttons: ButtonTracker,
+
+    active: Option<usize>,
+    passthrough: bool, // as of the last step
+    gamepads_loaded: bool,
+    has_debug_logged: bool,
+}
+
+impl<'lua, R> Performer<'lua, R>
+where
+    R: Fn() -> Result<(), gilrs::ff::Error> + Send + Sync + Clone + 'static,
+{
+    pub fn new(
+        config_mx: Arc<Mutex<Config>>,
+        output: Arc<dyn OutputSink>,
+        window: Option<tauri::WebviewWindow>,
+        rumble: Option<Rumble<R>>,
+        lua: Option<&'lua mlua::Lua>,
+        macros: MacroRunner,
+    ) -> Self {
+        Self {
+            config_mx,
+            output,
+            window,
+            rumble,
+            lua,
+            macros,
+            l_stick: Vec2::default(),
+            r_stick: Vec2::default(),
+            remainder: Vec2::default(),
+            l_state: StickState::default(),
+            r_state: StickState::default(),
+            l_trigger: 0.0,
+            r_trigger: 0.0,
+            scroll_remainder: Vec2::default(),
+            last_tick: None,
+            buttons: ButtonTracker::default(),
+            active: None,
+            passthrough: false,
+            gamepads_loaded: false,
+            has_debug_logged: false,
+        }
+    }
+
+    pub fn run(&mut self, input: &mut impl InputSource) -> Result<()> {
+        loop {
+            let wait = self.step(input)?;
+            input.wait(wait);
+        }
+    }
+
+    // handle all pending events and move the cursor once
+    // returns how long to wait for an event before the next step, which is a
+    // tick while something is moving and much longer when everything is idle
+    pub fn step(&mut self, input: &mut impl InputSource) -> Result<Duration> {
+        self.step_at(input, Instant::now())
+    }
+
+    pub fn step_at(&mut self, input: &mut impl InputSource, now: Instant) -> Result<Duration> {
+        if !self.gamepads_loaded {
+            let gamepads = input.gamepads();
+            log::info!("Connected gamepads: {:?}", gamepads);
+            self.config_mx.lock().unwrap().gamepads = gamepads;
+            self.gamepads_loaded = true;
+        }
+
+        self.update_active();
+
+        while let Some(event) = input.next_event() {
+            self.handle_event(event, now);
+        }
+
+        let config = self.config_mx.lock().unwrap();
+        let fired = self.buttons.tick(now, &config);
+        drop(config);
+        self.run_fired(fired);
+
+        self.update_passthrough();
+
+        if self.active.is_none() {
+            if !self.has_debug_logged {
+                log::debug!("No gamepad connected");
+                self.has_debug_logged = true;
+            }
+            return Ok(Duration::from_millis(NO_GAMEPAD_WAIT_MS));
+        }
+        self.has_debug_logged = false;
+
+        let moving = self.move_cursor(now)?;
+
+        let config = self.config_mx.lock().unwrap();
+        let mut wait = if moving {
+            config.tick_time()
+        } else {
+            Duration::from_millis(IDLE_WAIT_MS)
+        };
+
+        // wake up in time for chord timeouts, gestures and repeats
+        if let Some(deadline) = self.buttons.next_deadline(&config) {
+            wait = wait.min(deadline.saturating_duration_since(now));
+        }
+        Ok(wait)
+    }
+
+    // switches to the gamepad that should be listened to
+    // this is the only place stick state gets reset
+    fn update_active(&mut self) {
+        let active = self.select_gamepad();
+        if active != self.active {
+            // let go of everything the last gamepad was holding down
+            let fired = self.buttons.release_all();
+            self.run_fired(fired);
+            self.release_latched();
+
+            // don't carry stick state over from another gamepad
+            self.release_sticks();
+            self.l_stick = Vec2::default();
+            self.r_stick = Vec2::default();
+            self.remainder = Vec2::default();
+            self.l_trigger = 0.0;
+            self.r_trigger = 0.0;
+            self.scroll_remainder = Vec2::default();
+            self.last_tick = None;
+            self.active = active;
+        }
+    }
+
+    // passthrough can be turned on by an action or from the tray, either way
+    // nothing the bindings started should keep going while it's on
+    fn update_passthrough(&mut self) {
+        let passthrough = self.config_mx.lock().unwrap().passthrough;
+        if passthrough && !self.passthrough {
+            self.release_latched();
+            self.macros.cancel_all();
+        }
+        self.passthrough = passthrough;
+    }
+
+    // picks the gamepad to listen to and stores it in the config
+    fn select_gamepad(&self) -> Option<usize> {
+        let mut config = self.config_mx.lock().unwrap();
+        let active = choose_gamepad(
+            &config.gamepads,
+            config.gamepad_uuid.as_deref(),
+            config.gamepad_id,
+        );
+        if active != config.gamepad_id {
+            if let Some(id) = active {
+                log::info!("Using gamepad {}", id);
+            }
+            config.gamepad_id = active;
+        }
+        active
+    }
 
-    let mut has_debug_logged = false;
-    loop {
-        let mut config = config_mx.lock().unwrap();
+    fn handle_event(&mut self, event: InputEvent, now: Instant) {
+        match event.event {
+            InputEventType::Connected(gamepad) => return self.gamepad_connected(gamepad),
+            InputEventType::Disconnected => return self.gamepad_disconnected(event.id),
+            _ => (),
+        }
+
+        let config = self.config_mx.lock().unwrap();
+        if config.gamepad_id != Some(event.id) {
+            // ignore events from other gamepads
+            return;
+        }
+
+        match event.event {
+            // triggers are pressed by their analog value instead
+            InputEventType::ButtonPressed(button) | InputEventType::ButtonReleased(button)
+                if Trigger::from_button(button).is_some() => {}
+            InputEventType::ButtonPressed(button) => {
+                let fired = self.buttons.press(button, now, &config);
+
+                // drop the lock before calling the actions
+                drop(config);
 
-        // TODO: allow changing gamepad instead of just using the first one
-        match (gilrs.gamepads().next(), config.gamepad_id) {
-            (Some((gamepad_id, _gamepad)), None) => {
-                config.gamepad_id = Some(gamepad_id);
+                self.run_fired(fired);
             }
-            (None, _) => {
-                config.gamepad_id = None;
+            InputEventType::ButtonReleased(button) => {
+                let fired = self.buttons.release(button, now, &config);
 
-                l_stick = Vec2::default();
-                r_stick = Vec2::default();
-                remainder = Vec2::default();
+                // drop the lock before calling the actions
+                drop(config);
 
-                if !has_debug_logged {
-                    log::debug!("No gamepad connected");
-                    has_debug_logged = true;
+                self.run_fired(fired);
+            }
+            InputEventType::ButtonChanged(button, value) => {
+                let trigger = match Trigger::from_button(button) {
+                    Some(trigger) => trigger,
+                    None => return,
+                };
+                match trigger {
+                    Trigger::Left => self.l_trigger = value,
+                    Trigger::Right => self.r_trigger = value,
                 }
 
-                // drop the lock before sleeping
+                let was_pressed = self.buttons.is_pressed(button);
+                let fired = match (
+                    was_pressed,
+                    config.trigger(trigger).pressed(value, was_pressed),
+                ) {
+                    (false, true) => self.buttons.press(button, now, &config),
+                    (true, false) => self.buttons.release(button, now, &config),
+                    _ => vec![],
+                };
+
+                // drop the lock before calling the actions
                 drop(config);
-                thread::sleep(Duration::from_millis(2000));
-                continue;
+
+                self.run_fired(fired);
             }
-            _ => {}
+            InputEventType::AxisChanged(axis, value) => match axis {
+                Axis::LeftStickX => self.l_stick.x = value,
+                Axis::LeftStickY => self.l_stick.y = value,
+                Axis::RightStickX => self.r_stick.x = value,
+                Axis::RightStickY => self.r_stick.y = value,
+                _ => (),
+            },
+            InputEventType::Connected(_) | InputEventType::Disconnected => (),
         }
-        has_debug_logged = false;
+    }
+
+    fn gamepad_connected(&mut self, gamepad: GamepadInfo) {
+        log::info!("Gamepad connected: {:?}", gamepad);
+        self.emit_gamepad_event("gamepad_connected", gamepad.id, &gamepad.name);
+
+        let mut config = self.config_mx.lock().unwrap();
+        config.gamepads.retain(|g| g.id != gamepad.id);
+        config.gamepads.push(gamepad);
+        drop(config);
 
-        while let Some(event) = gilrs.next_event() {
-            match config.gamepad_id {
-                Some(id) => {
-                    if event.id != id {
-                        // ignore events from other gamepads
-                        continue;
+        self.update_active();
+    }
+
+    fn gamepad_disconnected(&mut self, id: usize) {
+        let mut config = self.config_mx.lock().unwrap();
+        let name = config
+            .gamepads
+            .iter()
+            .find(|g| g.id == id)
+            .map(|g| g.name.clone())
+            .unwrap_or_default();
+        config.gamepads.retain(|g| g.id != id);
+        drop(config);
+
+        log::info!("Gamepad disconnected: {} ({})", id, name);
+        self.emit_gamepad_event("gamepad_disconnected", id, &name);
+
+        self.update_active();
+    }
+
+    fn emit_gamepad_event(&self, event: &str, id: usize, name: &str) {
+        if let Some(window) = &self.window {
+            let payload = GamepadEvent {
+                id,
+                name: name.to_string(),
+            };
+            if let Err(e) = window.emit(event, payload) {
+                log::error!("Could not emit {}: {:?}", event, e);
+            }
+        }
+    }
+
+    fn action_interface(&self, binding: String) -> ActionInterface<'lua, R> {
+        ActionInterface {
+            config: self.config_mx.clone(),
+            window: self.window.clone(),
+            lua: self.lua,
+            rumble: self.rumble.clone(),
+            output: self.output.clone(),
+            macros: self.macros.clone(),
+            binding,
+        }
+    }
+
+    fn run_fired(&self, fired: Vec<Fired>) {
+        for f in fired {
+            match f {
+                Fired::Down(binding, actions) => self.run_actions(&binding, &actions, true),
+                Fired::Up(binding, actions) => self.run_actions(&binding, &actions, false),
+            }
+        }
+    }
+
+    fn run_actions(&self, binding: &str, actions: &[Action], down: bool) {
+        for (i, action) in actions.iter().enumerate() {
+            // actions in the same binding are told apart by their position
+            let action_interface = self.action_interface(format!("{}[{}]", binding, i));
+            let res = if down {
+                action.down(&action_interface)
+            } else {
+                action.up(&action_interface)
+            };
+            if let Err(e) = res {
+                log::error!("Error: {:?}", e);
+                if let Some(window) = &self.window {
+                    if let Err(e) = window.emit("action_error", e.to_string()) {
+                        log::error!("Could not emit action_error: {:?}", e);
                     }
                 }
-                None => {
-                    // set the gamepad id if it's not set
-                    config.gamepad_id = Some(event.id);
-                }
             }
+        }
+    }
 
-            match event {
-                Event {
-                    event: EventType::ButtonPressed(button, _),
-                    ..
-                } => {
-                    let actions = config.actions[button].clone();
-
-                    let action_interface = ActionInterface {
-                        config: config_mx.clone(),
-                        window: window.clone(),
-                        lua: Some(&lua_ctx),
-                        rumble: Some(rumble.clone()),
-                    };
-
-                    // drop the lock before calling the actions
-                    drop(config);
-
-                    for action in actions {
-                        if let Err(e) = action.down(&action_interface) {
-                            log::error!("Error: {:?}", e);
-                        }
-                    }
+    // lets go of the actions toggled on
+    fn release_latched(&self) {
+        let latched = std::mem::take(&mut self.config_mx.lock().unwrap().latched);
+        if latched.is_empty() {
+            return;
+        }
+        self.run_actions("latched", &latched, false);
+        if let Some(window) = &self.window {
+            if let Err(e) = window.emit("latch_change", Vec::<Action>::new()) {
+                log::error!("Could not emit latch_change: {:?}", e);
+            }
+        }
+    }
+
+    // lets go of any keys the sticks are holding and forgets radial selections
+    fn release_sticks(&mut self) {
+        let mut keys = self.l_state.release_keys();
+        keys.extend(self.r_state.release_keys());
+        self.l_state.cancel_radial();
+        self.r_state.cancel_radial();
+        self.press_keys(&keys, false);
+    }
 
-                    // reacquire the lock
-                    config = config_mx.lock().unwrap();
+    fn press_keys(&self, keys: &[rdev::Key], down: bool) {
+        let actions = keys
+            .iter()
+            .map(|&key| Action::KeyPress {
+                key,
+                modifiers: vec![],
+            })
+            .collect::<Vec<_>>();
+        self.run_actions("stick_keys", &actions, down);
+    }
+
+    // moves for the time since the last tick, returns whether anything is still moving
+    fn move_cursor(&mut self, now: Instant) -> Result<bool> {
+        let config = self.config_mx.lock().unwrap();
+        if config.gamepad_id.is_none() {
+            return Ok(false);
+        }
+        if config.passthrough {
+            drop(config);
+            self.release_sticks();
+            self.last_tick = None;
+            return Ok(false);
+        }
+
+        // the first tick after being idle moves for a whole tick
+        let max_tick_time = Duration::from_millis(MAX_TICK_TIME_MS).max(config.tick_time());
+        let elapsed = match self.last_tick {
+            Some(last) => now.saturating_duration_since(last).min(max_tick_time),
+            None => config.tick_time(),
+        };
+        let elapsed_ms = elapsed.as_secs_f32() * 1000.0;
+
+        let mut cursor = Vec2::<f32>::default();
+        let mut scroll = Vec2::<f32>::default();
+        let mut released = vec![];
+        let mut pressed = vec![];
+        let mut radial_fired = vec![];
+        let mut radial_changed = vec![];
+        for stick in [Stick::Left, Stick::Right] {
+            let (raw, state) = match stick {
+                Stick::Left => (self.l_stick, &mut self.l_state),
+                Stick::Right => (self.r_stick, &mut self.r_state),
+            };
+            let value = config.stick(stick).apply(raw);
+            let role = config.stick_role(stick);
+
+            match role.key_stick() {
+                Some(keys) => {
+                    let (r, p) = state.update_keys(value, keys);
+                    released.extend(r);
+                    pressed.extend(p);
                 }
+                // the role might have just changed
+                None => released.extend(state.release_keys()),
+            }
 
-                Event {
-                    event: EventType::ButtonReleased(button, _),
-                    ..
-                } => {
-                    let actions = config.actions[button].clone();
-
-                    let action_interface: ActionInterface<
-                        '_,
-                        fn() -> Result<(), gilrs::ff::Error>,
-                    > = ActionInterface {
-                        config: config_mx.clone(),
-                        window: window.clone(),
-                        lua: Some(&lua_ctx),
-                        rumble: None,
-                    };
-
-                    // drop the lock before calling the actions
-                    drop(config);
-
-                    for action in actions {
-                        if let Err(e) = action.up(&action_interface) {
-                            log::error!("Error: {:?}", e);
-                        }
+            let selected = state.selected();
+            match role {
+                StickRole::Cursor => {
+                    cursor.x += value.x;
+                    cursor.y += value.y;
+                }
+                StickRole::Scroll => {
+                    scroll.x += value.x;
+                    scroll.y += value.y;
+                }
+                StickRole::Radial(sectors) => {
+                    if let Some(i) = state.update_radial(value, sectors.len()) {
+                        let binding = format!("radial.{:?}.{}", stick, i);
+                        radial_fired.push((binding, sectors[i].clone()));
                     }
-
-                    // reacquire the lock
-                    config = config_mx.lock().unwrap();
                 }
-                Event {
-                    event: EventType::AxisChanged(axis, value, ..),
-                    ..
-                } => match axis {
-                    Axis::LeftStickX => l_stick.x = value,
-                    Axis::LeftStickY => l_stick.y = value,
-                    Axis::RightStickX => r_stick.x = value,
-                    Axis::RightStickY => r_stick.y = value,
-                    _ => (),
-                },
-                _ => (),
-            };
+                StickRole::ArrowKeys | StickRole::Wasd | StickRole::Keys(_) | StickRole::None => {
+                    state.cancel_radial()
+                }
+            }
+            if state.selected() != selected {
+                radial_changed.push(RadialEvent {
+                    stick,
+                    sector: state.selected(),
+                });
+            }
         }
 
-        if config.gamepad_id.is_none() {
-            continue;
-        }
-
-        let new_x = ease(l_stick.x)
-            * config.speed
-            * config.speed_mult
-            * UNIT_MULTIPLIER
-            * POLL_TIME_MS as f32
-            + remainder.x;
-        let new_y = -ease(l_stick.y)
-            * config.speed
-            * config.speed_mult
-            * UNIT_MULTIPLIER
-            * POLL_TIME_MS as f32
-            + remainder.y;
+        let speed_mult = config.speed_mult
+            * config.left_trigger.speed_mult(self.l_trigger)
+            * config.right_trigger.speed_mult(self.r_trigger);
+        let new_x =
+            cursor.x * config.speed * speed_mult * UNIT_MULTIPLIER * elapsed_ms + self.remainder.x;
+        let new_y =
+            -cursor.y * config.speed * speed_mult * UNIT_MULTIPLIER * elapsed_ms + self.remainder.y;
+
+        // scrolling is accumulated in the smallest steps the output can send
+        let steps = self.output.scroll_resolution() as f32 * elapsed.as_secs_f32();
+        let trigger_scroll = config.left_trigger.scroll_speed(self.l_trigger)
+            + config.right_trigger.scroll_speed(self.r_trigger);
+        let moving =
+            cursor != Vec2::default() || scroll != Vec2::default() || trigger_scroll != 0.0;
+        self.last_tick = if moving { Some(now) } else { None };
+        let scroll_x = scroll.x * config.scroll_speed * steps + self.scroll_remainder.x;
+        let scroll_y =
+            (scroll.y * config.scroll_speed + trigger_scroll) * steps + self.scroll_remainder.y;
+        drop(config);
+
         let (dx, x_rem) = integer_and_fractional(new_x);
         let (dy, y_rem) = integer_and_fractional(new_y);
-        remainder.x = x_rem;
-        remainder.y = y_rem;
+        self.remainder.x = x_rem;
+        self.remainder.y = y_rem;
 
         if (dx != 0) || (dy != 0) {
-            mouse.move_relative(dx, dy)?;
+            self.output.move_relative(dx, dy)?;
+        }
+
+        let (sx, sx_rem) = integer_and_fractional(scroll_x);
+        let (sy, sy_rem) = integer_and_fractional(scroll_y);
+        self.scroll_remainder.x = sx_rem;
+        self.scroll_remainder.y = sy_rem;
+
+        if (sx != 0) || (sy != 0) {
+            self.output.scroll_fine(sx, sy)?;
         }
 
-        if r_stick.y > 0.5 {
-            mouse.scroll_wheel(&mouce::common::ScrollDirection::Up)?;
-        } else if r_stick.y < -0.5 {
-            mouse.scroll_wheel(&mouce::common::ScrollDirection::Down)?;
+        self.press_keys(&released, false);
+        self.press_keys(&pressed, true);
+
+        for event in radial_changed {
+            if let Some(window) = &self.window {
+                if let Err(e) = window.emit("radial_change", event) {
+                    log::error!("Could not emit radial_change: {:?}", e);
+                }
+            }
         }
-        if r_stick.x > 0.5 {
-            mouse.scroll_wheel(&mouce::common::ScrollDirection::Right)?;
-        } else if r_stick.x < -0.5 {
-            mouse.scroll_wheel(&mouce::common::ScrollDirection::Left)?;
+        for (binding, actions) in radial_fired {
+            self.run_actions(&binding, &actions, true);
+            self.run_actions(&binding, &actions, false);
         }
 
-        drop(config);
-        thread::sleep(Duration::from_millis(POLL_TIME_MS));
+        Ok(moving)
+    }
+}
+
+// prefers the gamepad saved in the config, then the current one, then the first connected
+// identical gamepads share a uuid, so the current one wins if it matches the saved uuid
+fn choose_gamepad(
+    gamepads: &[GamepadInfo],
+    saved_uuid: Option<&str>,
+    current: Option<usize>,
+) -> Option<usize> {
+    let current = current.and_then(|id| gamepads.iter().find(|g| g.id == id));
+    if let Some(uuid) = saved_uuid {
+        if let Some(gamepad) = current.filter(|g| g.uuid == uuid) {
+            return Some(gamepad.id);
+        }
+        if let Some(gamepad) = gamepads.iter().find(|g| g.uuid == uuid) {
+            return Some(gamepad.id);
+        }
+    }
+    if let Some(gamepad) = current {
+        return Some(gamepad.id);
     }
+    gamepads.first().map(|g| g.id)
 }
 
 fn integer_and_fractional(num: f32) -> (i32, f32) {
@@ -242,3 +617,1053 @@ fn integer_and_fractional(num: f32) -> (i32, f32) {
         (num.ceil() as i32, num % 1.0)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::actions::MouseButton;
+    use crate::input::ScriptedInput;
+    use crate::output::{OutputEvent, RecordingOutput};
+    use gilrs::Button;
+
+    type NoRumble = fn() -> Result<(), gilrs::ff::Error>;
+
+    // one tick at the default tick rate
+    const TICK_TIME_MS: u64 = 4;
+
+    // steps once a tick starting at `start`, returns when the next tick would be
+    fn run_ticks(
+        performer: &mut Performer<'static, NoRumble>,
+        input: &mut ScriptedInput,
+        start: Instant,
+        ticks: u32,
+    ) -> Instant {
+        let tick = performer.config_mx.lock().unwrap().tick_time();
+        for i in 0..ticks {
+            performer.step_at(input, start + tick * i).unwrap();
+        }
+        start + tick * ticks
+    }
+
+    fn performer(
+        config: Config,
+    ) -> (
+        Arc<Mutex<Config>>,
+        Arc<RecordingOutput>,
+        Performer<'static, NoRumble>,
+    ) {
+        let config_mx = Arc::new(Mutex::new(config));
+        let output = Arc::new(RecordingOutput::default());
+        let performer = Performer::new(
+            config_mx.clone(),
+            output.clone(),
+            None,
+            None,
+            None,
+            MacroRunner::default(),
+        );
+        (config_mx, output, performer)
+    }
+
+    #[test]
+    fn binding_runs_on_press() {
+        let mut config = Config::default();
+        config.actions = toml::from_str("DPadUp = [\"speed_inc\"]").unwrap();
+        let speed = config.speed;
+        let step = config.speed_step;
+        let (config_mx, _, mut performer) = performer(config);
+
+        let mut input = ScriptedInput::new(vec![0]);
+        input.press(0, Button::DPadUp).release(0, Button::DPadUp);
+        performer.step(&mut input).unwrap();
+
+        assert_eq!(config_mx.lock().unwrap().speed, speed + step);
+    }
+
+    #[test]
+    fn other_gamepads_are_ignored() {
+        let mut config = Config::default();
+        config.actions = toml::from_str("DPadUp = [\"speed_inc\"]").unwrap();
+        let speed = config.speed;
+        let (config_mx, _, mut performer) = performer(config);
+
+        let mut input = ScriptedInput::new(vec![0, 1]);
+        input.press(1, Button::DPadUp);
+        performer.step(&mut input).unwrap();
+
+        assert_eq!(config_mx.lock().unwrap().speed, speed);
+    }
+
+    #[test]
+    fn saved_gamepad_is_preferred() {
+        let mut config = Config::default();
+        config.actions = toml::from_str("DPadUp = [\"speed_inc\"]").unwrap();
+        let speed = config.speed;
+        let step = config.speed_step;
+        let (config_mx, _, mut performer) = performer(config);
+
+        let mut input = ScriptedInput::new(vec![0, 1]);
+        config_mx.lock().unwrap().gamepad_uuid = Some(input.gamepads()[1].uuid.clone());
+        input.press(0, Button::DPadUp).press(1, Button::DPadUp);
+        performer.step(&mut input).unwrap();
+
+        let config = config_mx.lock().unwrap();
+        assert_eq!(config.gamepad_id, Some(1));
+        assert_eq!(config.speed, speed + step);
+    }
+
+    #[test]
+    fn identical_gamepads_keep_the_chosen_one() {
+        let pad = |id| GamepadInfo {
+            id,
+            name: "Same gamepad".to_string(),
+            uuid: "same-uuid".to_string(),
+            ff_supported: false,
+        };
+        let gamepads = [pad(0), pad(1)];
+
+        assert_eq!(
+            choose_gamepad(&gamepads, Some("same-uuid"), Some(1)),
+            Some(1)
+        );
+        assert_eq!(
+            choose_gamepad(&gamepads, Some("same-uuid"), Some(0)),
+            Some(0)
+        );
+        assert_eq!(choose_gamepad(&gamepads, Some("same-uuid"), None), Some(0));
+        assert_eq!(choose_gamepad(&gamepads, Some("other"), Some(1)), Some(1));
+    }
+
+    #[test]
+    fn disconnecting_switches_gamepad_and_resets_sticks() {
+        let (config_mx, output, mut performer) = performer(Config::default());
+
+        let mut input = ScriptedInput::new(vec![0, 1]);
+        input.axis(0, Axis::LeftStickX, 1.0);
+        performer.step(&mut input).unwrap();
+        assert!(!output.take().is_empty());
+
+        input.disconnect(0);
+        performer.step(&mut input).unwrap();
+        assert_eq!(config_mx.lock().unwrap().gamepad_id, Some(1));
+        assert!(output.take().is_empty());
+    }
+
+    #[test]
+    fn disconnecting_releases_held_actions() {
+        let mut config = Config::default();
+        config.actions = toml::from_str(
+            "South = [{ click = \"Left\" }]\nLeftBumper = [{ layer = \"nav\" }]\nEast = [{ toggle = { click = \"Right\" } }]",
+        )
+        .unwrap();
+        config.layers = toml::from_str("[nav]").unwrap();
+        let (config_mx, output, mut performer) = performer(config);
+
+        let mut input = ScriptedInput::new(vec![0, 1]);
+        input
+            .press(0, Button::South)
+            .press(0, Button::LeftTrigger)
+            .press(0, Button::East)
+            .release(0, Button::East);
+        performer.step(&mut input).unwrap();
+        assert_eq!(
+            output.take(),
+            vec![
+                OutputEvent::ButtonDown(MouseButton::Left),
+                OutputEvent::ButtonDown(MouseButton::Right),
+            ]
+        );
+        assert_eq!(config_mx.lock().unwrap().current_layer(), "nav");
+
+        input.disconnect(0);
+        performer.step(&mut input).unwrap();
+        let mut released = output.take();
+        released.sort_by_key(|event| format!("{:?}", event));
+        assert_eq!(
+            released,
+            vec![
+                OutputEvent::ButtonUp(MouseButton::Left),
+                OutputEvent::ButtonUp(MouseButton::Right),
+            ]
+        );
+        let config = config_mx.lock().unwrap();
+        assert_eq!(config.gamepad_id, Some(1));
+        assert_eq!(config.current_layer(), "default");
+        assert!(config.latched.is_empty());
+    }
+
+    #[test]
+    fn connecting_saved_gamepad_switches_to_it() {
+        let (config_mx, _, mut performer) = performer(Config::default());
+
+        let mut input = ScriptedInput::new(vec![0]);
+        performer.step(&mut input).unwrap();
+        assert_eq!(config_mx.lock().unwrap().gamepad_id, Some(0));
+
+        input.connect(1);
+        let uuid = input.gamepads()[1].uuid.clone();
+        config_mx.lock().unwrap().gamepad_uuid = Some(uuid);
+        performer.step(&mut input).unwrap();
+        assert_eq!(config_mx.lock().unwrap().gamepad_id, Some(1));
+    }
+
+    #[test]
+    fn passthrough_suspends_movement_and_bindings() {
+        let mut config = Config::default();
+        config.actions = toml::from_str("South = [{ click = \"Left\" }]").unwrap();
+        let (config_mx, output, mut performer) = performer(config);
+
+        let mut input = ScriptedInput::new(vec![0]);
+        input
+            .press(0, Button::Select)
+            .press(0, Button::Start)
+            .release(0, Button::Select)
+            .release(0, Button::Start)
+            .axis(0, Axis::LeftStickX, 1.0)
+            .press(0, Button::South);
+        performer.step(&mut input).unwrap();
+        assert!(config_mx.lock().unwrap().passthrough);
+        assert!(output.take().is_empty());
+
+        // the escape chord switches back
+        input.press(0, Button::Start).press(0, Button::Select);
+        performer.step(&mut input).unwrap();
+        assert!(!config_mx.lock().unwrap().passthrough);
+        assert!(!output.take().is_empty());
+    }
+
+    #[test]
+    fn chord_suppresses_single_bindings() {
+        let config = Config::parse(
+            "[actions]\nLeftBumper = [{ click = \"Right\" }]\nSouth = [{ click = \"Left\" }]\n[[chords]]\nbuttons = [\"LeftBumper\", \"South\"]\nactions = [{ click = \"Middle\" }]",
+        )
+        .unwrap();
+        let (_, output, mut performer) = performer(config);
+
+        let start = Instant::now();
+        let mut input = ScriptedInput::new(vec![0]);
+        input
+            .press(0, Button::LeftTrigger)
+            .press(0, Button::South)
+            .release(0, Button::South)
+            .release(0, Button::LeftTrigger);
+        performer.step_at(&mut input, start).unwrap();
+        assert_eq!(
+            output.take(),
+            vec![
+                OutputEvent::ButtonDown(MouseButton::Middle),
+                OutputEvent::ButtonUp(MouseButton::Middle)
+            ]
+        );
+    }
+
+    #[test]
+    fn chord_button_acts_alone_after_timeout() {
+        let config = Config::parse(
+            "[actions]\nLeftBumper = [{ click = \"Right\" }]\n[[chords]]\nbuttons = [\"LeftBumper\", \"South\"]\nactions = [{ click = \"Middle\" }]",
+        )
+        .unwrap();
+        let timeout = Duration::from_millis(config.chord_timeout_ms);
+        let (_, output, mut performer) = performer(config);
+
+        let start = Instant::now();
+        let mut input = ScriptedInput::new(vec![0]);
+        input.press(0, Button::LeftTrigger);
+        performer.step_at(&mut input, start).unwrap();
+        assert!(output.take().is_empty());
+
+        performer.step_at(&mut input, start + timeout).unwrap();
+        assert_eq!(
+            output.take(),
+            vec![OutputEvent::ButtonDown(MouseButton::Right)]
+        );
+
+        input.release(0, Button::LeftTrigger);
+        performer.step_at(&mut input, start + timeout * 2).unwrap();
+        assert_eq!(
+            output.take(),
+            vec![OutputEvent::ButtonUp(MouseButton::Right)]
+        );
+    }
+
+    fn gesture_config() -> Config {
+        Config::parse(
+            "[gestures.West]\ntap = [{ click = \"Left\" }]\nhold = [{ click = \"Right\" }]\ndouble_tap = [{ click = \"Middle\" }]",
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn repeat_only_refires_presses() {
+        let config = Config::parse(
+            "[actions]\nDPadDown = [{ keypress = { key = \"DownArrow\", modifiers = [] } }, { toggle = { click = \"Right\" } }]\n[repeat.DPadDown]\ndelay_ms = 100",
+        )
+        .unwrap();
+        let (config_mx, output, mut performer) = performer(config);
+
+        let start = Instant::now();
+        let mut input = ScriptedInput::new(vec![0]);
+        input.press(0, Button::DPadDown);
+        performer.step_at(&mut input, start).unwrap();
+        assert_eq!(
+            output.take(),
+            vec![
+                OutputEvent::KeyDown(rdev::Key::DownArrow),
+                OutputEvent::ButtonDown(MouseButton::Right),
+            ]
+        );
+
+        // the toggle stays on instead of flipping with every repeat
+        performer
+            .step_at(&mut input, start + Duration::from_millis(100))
+            .unwrap();
+        assert_eq!(
+            output.take(),
+            vec![
+                OutputEvent::KeyUp(rdev::Key::DownArrow),
+                OutputEvent::KeyDown(rdev::Key::DownArrow),
+            ]
+        );
+        assert_eq!(config_mx.lock().unwrap().latched.len(), 1);
+    }
+
+    #[test]
+    fn held_button_repeats() {
+        let config = Config::parse(
+            "[actions]\nDPadDown = [{ keypress = { key = \"DownArrow\", modifiers = [] } }]\n[repeat.DPadDown]\ndelay_ms = 100\nrate = 20.0",
+        )
+        .unwrap();
+        let (_, output, mut performer) = performer(config);
+        let ms = Duration::from_millis;
+        let down = OutputEvent::KeyDown(rdev::Key::DownArrow);
+        let up = OutputEvent::KeyUp(rdev::Key::DownArrow);
+
+        let start = Instant::now();
+        let mut input = ScriptedInput::new(vec![0]);
+        input.press(0, Button::DPadDown);
+        performer.step_at(&mut input, start).unwrap();
+        assert_eq!(output.take(), vec![down.clone()]);
+
+        performer.step_at(&mut input, start + ms(99)).unwrap();
+        assert!(output.take().is_empty());
+
+        performer.step_at(&mut input, start + ms(100)).unwrap();
+        assert_eq!(output.take(), vec![up.clone(), down.clone()]);
+
+        performer.step_at(&mut input, start + ms(149)).unwrap();
+        assert!(output.take().is_empty());
+
+        performer.step_at(&mut input, start + ms(150)).unwrap();
+        assert_eq!(output.take(), vec![up.clone(), down]);
+
+        input.release(0, Button::DPadDown);
+        performer.step_at(&mut input, start + ms(160)).unwrap();
+        assert_eq!(output.take(), vec![up]);
+
+        performer.step_at(&mut input, start + ms(300)).unwrap();
+        assert!(output.take().is_empty());
+    }
+
+    #[test]
+    fn tap_fires_after_double_tap_window() {
+        let config = gesture_config();
+        let window = Duration::from_millis(config.double_tap_ms + 1);
+        let (_, output, mut performer) = performer(config);
+
+        let start = Instant::now();
+        let mut input = ScriptedInput::new(vec![0]);
+        input.press(0, Button::West).release(0, Button::West);
+        performer.step_at(&mut input, start).unwrap();
+        assert!(output.take().is_empty());
+
+        performer.step_at(&mut input, start + window).unwrap();
+        assert_eq!(
+            output.take(),
+            vec![
+                OutputEvent::ButtonDown(MouseButton::Left),
+                OutputEvent::ButtonUp(MouseButton::Left)
+            ]
+        );
+    }
+
+    #[test]
+    fn hold_fires_after_hold_time() {
+        let config = gesture_config();
+        let hold_time = Duration::from_millis(config.hold_time_ms);
+        let (_, output, mut performer) = performer(config);
+
+        let start = Instant::now();
+        let mut input = ScriptedInput::new(vec![0]);
+        input.press(0, Button::West);
+        performer.step_at(&mut input, start).unwrap();
+        performer.step_at(&mut input, start + hold_time).unwrap();
+        assert_eq!(
+            output.take(),
+            vec![OutputEvent::ButtonDown(MouseButton::Right)]
+        );
+
+        input.release(0, Button::West);
+        performer
+            .step_at(&mut input, start + hold_time * 2)
+            .unwrap();
+        assert_eq!(
+            output.take(),
+            vec![OutputEvent::ButtonUp(MouseButton::Right)]
+        );
+    }
+
+    #[test]
+    fn double_tap_fires_on_second_press() {
+        let (_, output, mut performer) = performer(gesture_config());
+
+        let start = Instant::now();
+        let mut input = ScriptedInput::new(vec![0]);
+        input.press(0, Button::West).release(0, Button::West);
+        performer.step_at(&mut input, start).unwrap();
+
+        input.press(0, Button::West);
+        performer
+            .step_at(&mut input, start + Duration::from_millis(100))
+            .unwrap();
+        assert_eq!(
+            output.take(),
+            vec![OutputEvent::ButtonDown(MouseButton::Middle)]
+        );
+
+        input.release(0, Button::West);
+        performer
+            .step_at(&mut input, start + Duration::from_millis(150))
+            .unwrap();
+        assert_eq!(
+            output.take(),
+            vec![OutputEvent::ButtonUp(MouseButton::Middle)]
+        );
+    }
+
+    #[test]
+    fn layer_binding_takes_over_from_gestures() {
+        let mut config = gesture_config();
+        config.actions = toml::from_str("LeftBumper = [{ layer = \"nav\" }]").unwrap();
+        config.layers = toml::from_str("[nav]\nWest = [{ click = \"Right\" }]").unwrap();
+        let (_, output, mut performer) = performer(config);
+
+        // fires on press instead of waiting to see which gesture it is
+        let mut input = ScriptedInput::new(vec![0]);
+        input.press(0, Button::LeftTrigger).press(0, Button::West);
+        performer.step(&mut input).unwrap();
+        assert_eq!(
+            output.take(),
+            vec![OutputEvent::ButtonDown(MouseButton::Right)]
+        );
+
+        input.release(0, Button::West);
+        performer.step(&mut input).unwrap();
+        assert_eq!(
+            output.take(),
+            vec![OutputEvent::ButtonUp(MouseButton::Right)]
+        );
+    }
+
+    #[test]
+    fn layers_fall_through_to_lower_layers() {
+        let mut config = Config::default();
+        config.actions = toml::from_str(
+            "LeftBumper = [{ layer = \"mouse\" }]\nSouth = [{ click = \"Left\" }]\nEast = [{ click = \"Right\" }]",
+        )
+        .unwrap();
+        config.layers = toml::from_str("[mouse]\nSouth = [{ click = \"Middle\" }]").unwrap();
+        let (config_mx, output, mut performer) = performer(config);
+
+        let mut input = ScriptedInput::new(vec![0]);
+        input
+            .press(0, Button::LeftTrigger)
+            .press(0, Button::South)
+            .release(0, Button::South)
+            .press(0, Button::East)
+            .release(0, Button::East);
+        performer.step(&mut input).unwrap();
+        assert_eq!(config_mx.lock().unwrap().current_layer(), "mouse");
+        assert_eq!(
+            output.take(),
+            vec![
+                OutputEvent::ButtonDown(MouseButton::Middle),
+                OutputEvent::ButtonUp(MouseButton::Middle),
+                OutputEvent::ButtonDown(MouseButton::Right),
+                OutputEvent::ButtonUp(MouseButton::Right),
+            ]
+        );
+
+        input.release(0, Button::LeftTrigger);
+        performer.step(&mut input).unwrap();
+        assert_eq!(config_mx.lock().unwrap().current_layer(), "default");
+    }
+
+    #[test]
+    fn toggle_latches_until_pressed_again() {
+        let mut config = Config::default();
+        config.actions = toml::from_str(
+            "South = [{ toggle = { click = \"Left\" } }]\nEast = [{ toggle = { keypress = { key = \"KeyA\", modifiers = [\"Shift\"] } } }]",
+        )
+        .unwrap();
+        let (config_mx, output, mut performer) = performer(config);
+
+        let mut input = ScriptedInput::new(vec![0]);
+        input.press(0, Button::South).release(0, Button::South);
+        input.press(0, Button::East).release(0, Button::East);
+        performer.step(&mut input).unwrap();
+        assert_eq!(
+            output.take(),
+            vec![
+                OutputEvent::ButtonDown(MouseButton::Left),
+                OutputEvent::KeyDown(rdev::Key::ShiftLeft),
+                OutputEvent::KeyDown(rdev::Key::KeyA),
+            ]
+        );
+        assert_eq!(config_mx.lock().unwrap().latched.len(), 2);
+
+        input.press(0, Button::South).release(0, Button::South);
+        input.press(0, Button::East).release(0, Button::East);
+        performer.step(&mut input).unwrap();
+        assert_eq!(
+            output.take(),
+            vec![
+                OutputEvent::ButtonUp(MouseButton::Left),
+                OutputEvent::KeyUp(rdev::Key::KeyA),
+                OutputEvent::KeyUp(rdev::Key::ShiftLeft),
+            ]
+        );
+        assert!(config_mx.lock().unwrap().latched.is_empty());
+    }
+
+    #[test]
+    fn trigger_presses_past_threshold() {
+        let mut config = Config::default();
+        config.actions = toml::from_str("RightTrigger2 = [{ click = \"Left\" }]").unwrap();
+        let (_, output, mut performer) = performer(config);
+
+        let mut input = ScriptedInput::new(vec![0]);
+        input
+            .button_value(0, Button::RightTrigger2, 0.3)
+            .button_value(0, Button::RightTrigger2, 0.6)
+            .button_value(0, Button::RightTrigger2, 0.47);
+        performer.step(&mut input).unwrap();
+        assert_eq!(
+            output.take(),
+            vec![OutputEvent::ButtonDown(MouseButton::Left)]
+        );
+
+        input.button_value(0, Button::RightTrigger2, 0.1);
+        performer.step(&mut input).unwrap();
+        assert_eq!(
+            output.take(),
+            vec![OutputEvent::ButtonUp(MouseButton::Left)]
+        );
+    }
+
+    #[test]
+    fn trigger_speeds_up_cursor() {
+        let mut config = Config::default();
+        config.right_trigger = toml::from_str("role = { speed = 3.0 }\ndeadzone = 0.0").unwrap();
+        let speed = config.speed;
+        let (_, output, mut performer) = performer(config);
+
+        let mut input = ScriptedInput::new(vec![0]);
+        let now = Instant::now();
+        input
+            .axis(0, Axis::LeftStickX, 1.0)
+            .button_value(0, Button::RightTrigger2, 1.0);
+        run_ticks(&mut performer, &mut input, now, 100);
+
+        // a fully pulled trigger moves the cursor three times as far and nothing else
+        let moved: i32 = output
+            .take()
+            .into_iter()
+            .map(|event| match event {
+                OutputEvent::Move(dx, _) => dx,
+                event => panic!("unexpected {:?}", event),
+            })
+            .sum();
+        let expected = speed * 3.0 * UNIT_MULTIPLIER * TICK_TIME_MS as f32 * 100.0;
+        assert!((moved as f32 - expected).abs() <= 1.0);
+    }
+
+    #[test]
+    fn trigger_scrolls_proportionally() {
+        let mut config = Config::default();
+        config.left_trigger = toml::from_str("role = { scroll = -100.0 }\ndeadzone = 0.0").unwrap();
+        let (_, output, mut performer) = performer(config);
+
+        let mut input = ScriptedInput::new(vec![0]);
+        let now = Instant::now();
+        input.button_value(0, Button::LeftTrigger2, 0.5);
+        run_ticks(&mut performer, &mut input, now, 100);
+
+        // 50 notches a second for 100 ticks of 4 ms
+        let scrolled: i32 = output
+            .take()
+            .into_iter()
+            .map(|event| match event {
+                OutputEvent::Scroll(0, dy) => dy,
+                event => panic!("unexpected {:?}", event),
+            })
+            .sum();
+        assert!((scrolled + 20).abs() <= 1);
+    }
+
+    // total scroll recorded, whole notches and fine steps separately
+    fn scrolled(output: &RecordingOutput) -> (Vec2<i32>, Vec2<i32>) {
+        let (mut notches, mut steps) = (Vec2::default(), Vec2::default());
+        for event in output.take() {
+            match event {
+                OutputEvent::Scroll(dx, dy) => {
+                    notches.x += dx;
+                    notches.y += dy;
+                }
+                OutputEvent::FineScroll(dx, dy) => {
+                    steps.x += dx;
+                    steps.y += dy;
+                }
+                event => panic!("unexpected {:?}", event),
+            }
+        }
+        (notches, steps)
+    }
+
+    #[test]
+    fn right_stick_scrolls_proportionally() {
+        let config = Config::default();
+        let notches_per_tick = config.scroll_speed * TICK_TIME_MS as f32 / 1000.0;
+        let (_, output, mut performer) = performer(config);
+
+        let mut input = ScriptedInput::new(vec![0]);
+        let now = Instant::now();
+        input.axis(0, Axis::RightStickY, 1.0);
+        let now = run_ticks(&mut performer, &mut input, now, 500);
+        let (full, _) = scrolled(&output);
+        assert!((full.y as f32 - notches_per_tick * 500.0).abs() <= 1.0);
+        assert_eq!(full.x, 0);
+
+        // barely pushed scrolls slower, not at the same rate
+        input
+            .axis(0, Axis::RightStickY, 0.0)
+            .axis(0, Axis::RightStickX, -0.5);
+        run_ticks(&mut performer, &mut input, now, 500);
+        let (half, _) = scrolled(&output);
+        assert!(half.x < 0 && half.x > -full.y);
+    }
+
+    #[test]
+    fn fine_scroll_uses_output_resolution() {
+        let config = Config::default();
+        let steps_per_tick = config.scroll_speed * 120.0 * TICK_TIME_MS as f32 / 1000.0;
+        let config_mx = Arc::new(Mutex::new(config));
+        let output = Arc::new(RecordingOutput::with_fine_scroll());
+        let mut performer = Performer::<NoRumble>::new(
+            config_mx,
+            output.clone(),
+            None,
+            None,
+            None,
+            MacroRunner::default(),
+        );
+
+        let mut input = ScriptedInput::new(vec![0]);
+        let now = Instant::now();
+        input.axis(0, Axis::RightStickY, -1.0);
+        run_ticks(&mut performer, &mut input, now, 10);
+        let (notches, steps) = scrolled(&output);
+        assert_eq!(notches, Vec2::default());
+        assert!((steps.y as f32 + steps_per_tick * 10.0).abs() <= 1.0);
+    }
+
+    #[test]
+    fn swap_sticks_swaps_settings() {
+        let mut config = Config::default();
+        config.actions = toml::from_str("North = [\"swap_sticks\"]").unwrap();
+        config.left_stick.inner_deadzone = 0.3;
+        let (config_mx, output, mut performer) = performer(config);
+
+        let mut input = ScriptedInput::new(vec![0]);
+        let now = Instant::now();
+        input
+            .press(0, Button::North)
+            .release(0, Button::North)
+            .axis(0, Axis::RightStickX, 1.0);
+        run_ticks(&mut performer, &mut input, now, 10);
+
+        let config = config_mx.lock().unwrap();
+        assert_eq!(config.stick_role(Stick::Left), &StickRole::Scroll);
+        assert_eq!(config.stick_role(Stick::Right), &StickRole::Cursor);
+        assert_eq!(config.right_stick.inner_deadzone, 0.3);
+        assert_eq!(config.left_stick.inner_deadzone, 0.1);
+        assert!(output
+            .take()
+            .iter()
+            .all(|event| matches!(event, OutputEvent::Move(dx, 0) if *dx > 0)));
+    }
+
+    #[test]
+    fn stick_presses_direction_keys() {
+        let mut config = Config::default();
+        config.left_stick.role = Some(StickRole::Wasd);
+        let (_, output, mut performer) = performer(config);
+
+        let mut input = ScriptedInput::new(vec![0]);
+        input.axis(0, Axis::LeftStickY, 1.0);
+        performer.step(&mut input).unwrap();
+        performer.step(&mut input).unwrap();
+        assert_eq!(output.take(), vec![OutputEvent::KeyDown(rdev::Key::KeyW)]);
+
+        input.axis(0, Axis::LeftStickY, 0.0);
+        performer.step(&mut input).unwrap();
+        assert_eq!(output.take(), vec![OutputEvent::KeyUp(rdev::Key::KeyW)]);
+    }
+
+    #[test]
+    fn stick_presses_configured_keys_on_diagonals() {
+        let mut config = Config::default();
+        config.right_stick = toml::from_str(
+            "role = { keys = { up = \"KeyI\", right = \"KeyL\", down = \"KeyK\", left = \"KeyJ\", directions = 8 } }",
+        )
+        .unwrap();
+        let (_, output, mut performer) = performer(config);
+
+        let mut input = ScriptedInput::new(vec![0]);
+        input
+            .axis(0, Axis::RightStickX, 0.7)
+            .axis(0, Axis::RightStickY, 0.7);
+        performer.step(&mut input).unwrap();
+        assert_eq!(
+            output.take(),
+            vec![
+                OutputEvent::KeyDown(rdev::Key::KeyI),
+                OutputEvent::KeyDown(rdev::Key::KeyL),
+            ]
+        );
+
+        input.axis(0, Axis::RightStickX, 0.0);
+        performer.step(&mut input).unwrap();
+        assert_eq!(output.take(), vec![OutputEvent::KeyUp(rdev::Key::KeyL)]);
+    }
+
+    #[test]
+    fn radial_fires_selected_sector() {
+        let mut config = Config::default();
+        config.right_stick.role = Some(StickRole::Radial(vec![
+            vec![Action::Click(MouseButton::Left)],
+            vec![Action::Click(MouseButton::Right)],
+        ]));
+        let (_, output, mut performer) = performer(config);
+
+        let mut input = ScriptedInput::new(vec![0]);
+        input.axis(0, Axis::RightStickY, -1.0);
+        performer.step(&mut input).unwrap();
+        assert!(output.take().is_empty());
+
+        input.axis(0, Axis::RightStickY, 0.0);
+        performer.step(&mut input).unwrap();
+        assert_eq!(
+            output.take(),
+            vec![
+                OutputEvent::ButtonDown(MouseButton::Right),
+                OutputEvent::ButtonUp(MouseButton::Right),
+            ]
+        );
+    }
+
+    #[test]
+    fn type_text_types_off_thread() {
+        let mut config = Config::default();
+        config.actions =
+            toml::from_str("North = [{ type_text = { text = \"Ok\", delay_ms = 1 } }]").unwrap();
+        let (_, output, mut performer) = performer(config);
+
+        let mut input = ScriptedInput::new(vec![0]);
+        input.press(0, Button::North).release(0, Button::North);
+        performer.step(&mut input).unwrap();
+        performer.macros.wait_idle();
+
+        assert_eq!(
+            output.take(),
+            vec![
+                OutputEvent::KeyDown(rdev::Key::ShiftLeft),
+                OutputEvent::KeyDown(rdev::Key::KeyO),
+                OutputEvent::KeyUp(rdev::Key::KeyO),
+                OutputEvent::KeyUp(rdev::Key::ShiftLeft),
+                OutputEvent::KeyDown(rdev::Key::KeyK),
+                OutputEvent::KeyUp(rdev::Key::KeyK),
+            ]
+        );
+    }
+
+    #[test]
+    fn type_text_refuses_characters_it_cant_type() {
+        let mut config = Config::default();
+        config.actions =
+            toml::from_str("North = [{ type_text = { text = \"caf\u{e9}\", delay_ms = 0 } }]")
+                .unwrap();
+        let (_, output, mut performer) = performer(config);
+
+        let mut input = ScriptedInput::new(vec![0]);
+        input.press(0, Button::North).release(0, Button::North);
+        performer.step(&mut input).unwrap();
+        performer.macros.wait_idle();
+
+        // nothing is typed rather than the text with a gap in it
+        assert!(output.take().is_empty());
+    }
+
+    #[test]
+    fn macro_runs_steps_in_order() {
+        let mut config = Config::default();
+        config.actions = toml::from_str(
+            "West = [{ macro = [{ key_tap = \"KeyA\" }, { wait = 5 }, { type = \"Hi\" }, { click = \"Left\" }] }]",
+        )
+        .unwrap();
+        let (_, output, mut performer) = performer(config);
+
+        let mut input = ScriptedInput::new(vec![0]);
+        input.press(0, Button::West).release(0, Button::West);
+        performer.step(&mut input).unwrap();
+        performer.macros.wait_idle();
+
+        assert_eq!(
+            output.take(),
+            vec![
+                OutputEvent::KeyDown(rdev::Key::KeyA),
+                OutputEvent::KeyUp(rdev::Key::KeyA),
+                OutputEvent::KeyDown(rdev::Key::ShiftLeft),
+                OutputEvent::KeyDown(rdev::Key::KeyH),
+                OutputEvent::KeyUp(rdev::Key::KeyH),
+                OutputEvent::KeyUp(rdev::Key::ShiftLeft),
+                OutputEvent::KeyDown(rdev::Key::KeyI),
+                OutputEvent::KeyUp(rdev::Key::KeyI),
+                OutputEvent::ButtonDown(MouseButton::Left),
+                OutputEvent::ButtonUp(MouseButton::Left),
+            ]
+        );
+    }
+
+    #[test]
+    fn pressing_again_cancels_macro() {
+        let mut config = Config::default();
+        config.actions = toml::from_str(
+            "West = [{ macro = [{ key_down = \"KeyA\" }, { wait = 10000 }, { click = \"Left\" }] }]",
+        )
+        .unwrap();
+        let (_, output, mut performer) = performer(config);
+
+        let mut input = ScriptedInput::new(vec![0]);
+        input.press(0, Button::West).release(0, Button::West);
+        performer.step(&mut input).unwrap();
+        input.press(0, Button::West).release(0, Button::West);
+        performer.step(&mut input).unwrap();
+        performer.macros.wait_idle();
+
+        // the held key is let go and the rest of the macro never runs
+        let events = output.take();
+        let count = |event: OutputEvent| events.iter().filter(|e| **e == event).count();
+        assert_eq!(
+            count(OutputEvent::KeyDown(rdev::Key::KeyA)),
+            count(OutputEvent::KeyUp(rdev::Key::KeyA))
+        );
+        assert_eq!(count(OutputEvent::ButtonDown(MouseButton::Left)), 0);
+    }
+
+    #[test]
+    fn passthrough_releases_toggles_and_cancels_macros() {
+        let mut config = Config::default();
+        config.actions = toml::from_str(
+            "South = [{ toggle = { click = \"Left\" } }]\nWest = [{ macro = [{ key_down = \"KeyA\" }, { wait = 10000 }, { click = \"Right\" }] }]\nNorth = [\"toggle_mode\"]",
+        )
+        .unwrap();
+        let (config_mx, output, mut performer) = performer(config);
+
+        let mut input = ScriptedInput::new(vec![0]);
+        input.press(0, Button::South).release(0, Button::South);
+        input.press(0, Button::West).release(0, Button::West);
+        performer.step(&mut input).unwrap();
+        input.press(0, Button::North).release(0, Button::North);
+        performer.step(&mut input).unwrap();
+        performer.macros.wait_idle();
+        assert!(config_mx.lock().unwrap().passthrough);
+        assert!(config_mx.lock().unwrap().latched.is_empty());
+
+        let events = output.take();
+        let count = |event: OutputEvent| events.iter().filter(|e| **e == event).count();
+        assert_eq!(count(OutputEvent::ButtonUp(MouseButton::Left)), 1);
+        assert_eq!(
+            count(OutputEvent::KeyDown(rdev::Key::KeyA)),
+            count(OutputEvent::KeyUp(rdev::Key::KeyA))
+        );
+        assert_eq!(count(OutputEvent::ButtonDown(MouseButton::Right)), 0);
+    }
+
+    #[test]
+    fn same_macro_on_two_buttons_runs_twice() {
+        let mut config = Config::default();
+        config.actions = toml::from_str(
+            "West = [{ macro = [{ wait = 20 }, { click = \"Left\" }] }]\nNorth = [{ macro = [{ wait = 20 }, { click = \"Left\" }] }]",
+        )
+        .unwrap();
+        let (_, output, mut performer) = performer(config);
+
+        let mut input = ScriptedInput::new(vec![0]);
+        input
+            .press(0, Button::West)
+            .release(0, Button::West)
+            .press(0, Button::North)
+            .release(0, Button::North);
+        performer.step(&mut input).unwrap();
+        performer.macros.wait_idle();
+
+        // the second button starts its own run instead of cancelling the first,
+        // the runs overlap so only the count is checked
+        let events = output.take();
+        let count = |event: OutputEvent| events.iter().filter(|e| **e == event).count();
+        assert_eq!(count(OutputEvent::ButtonDown(MouseButton::Left)), 2);
+        assert_eq!(count(OutputEvent::ButtonUp(MouseButton::Left)), 2);
+    }
+
+    #[test]
+    fn click_binding_presses_and_releases() {
+        let mut config = Config::default();
+        config.actions = toml::from_str("South = [{ click = \"Left\" }]").unwrap();
+        let (_, output, mut performer) = performer(config);
+
+        let mut input = ScriptedInput::new(vec![0]);
+        input.press(0, Button::South);
+        performer.step(&mut input).unwrap();
+        assert_eq!(
+            output.take(),
+            vec![OutputEvent::ButtonDown(MouseButton::Left)]
+        );
+
+        input.release(0, Button::South);
+        performer.step(&mut input).unwrap();
+        assert_eq!(
+            output.take(),
+            vec![OutputEvent::ButtonUp(MouseButton::Left)]
+        );
+    }
+
+    #[test]
+    fn waits_for_events_when_idle() {
+        let config = Config::default();
+        let chord_timeout = Duration::from_millis(config.chord_timeout_ms);
+        let (_, _, mut performer) = performer(config);
+        let idle = Duration::from_millis(IDLE_WAIT_MS);
+        let tick = Duration::from_millis(TICK_TIME_MS);
+
+        let now = Instant::now();
+        let mut input = ScriptedInput::new(vec![0]);
+        assert_eq!(performer.step_at(&mut input, now).unwrap(), idle);
+
+        input.axis(0, Axis::LeftStickX, 0.05);
+        assert_eq!(performer.step_at(&mut input, now).unwrap(), idle);
+
+        input.axis(0, Axis::LeftStickX, 0.8);
+        assert_eq!(performer.step_at(&mut input, now).unwrap(), tick);
+
+        // select is part of the escape chord so it waits to see if start follows
+        input
+            .axis(0, Axis::LeftStickX, 0.0)
+            .press(0, Button::Select);
+        assert_eq!(performer.step_at(&mut input, now).unwrap(), chord_timeout);
+    }
+
+    #[test]
+    fn travel_is_the_same_at_any_tick_rate() {
+        let travel = |tick_rate: u32| {
+            let mut config = Config::default();
+            config.tick_rate = tick_rate;
+            let (_, output, mut performer) = performer(config);
+
+            let mut input = ScriptedInput::new(vec![0]);
+            input
+                .axis(0, Axis::LeftStickX, 0.7)
+                .axis(0, Axis::LeftStickY, -0.3)
+                .axis(0, Axis::RightStickY, 0.6);
+            // one second
+            run_ticks(&mut performer, &mut input, Instant::now(), tick_rate);
+
+            let (mut moved, mut scrolled) = (Vec2::<i32>::default(), 0);
+            for event in output.take() {
+                match event {
+                    OutputEvent::Move(dx, dy) => {
+                        moved.x += dx;
+                        moved.y += dy;
+                    }
+                    OutputEvent::Scroll(0, dy) => scrolled += dy,
+                    event => panic!("unexpected {:?}", event),
+                }
+            }
+            (moved, scrolled)
+        };
+
+        let (moved, scrolled) = travel(250);
+        assert!(moved.x > 0 && moved.y > 0 && scrolled > 0);
+        for tick_rate in [10, 15, 30, 60, 144, 1000] {
+            let (other_moved, other_scrolled) = travel(tick_rate);
+            assert!(
+                (other_moved.x - moved.x).abs() <= 1 && (other_moved.y - moved.y).abs() <= 1,
+                "{:?} at {} per second, {:?} at 250",
+                other_moved,
+                tick_rate,
+                moved
+            );
+            assert!((other_scrolled - scrolled).abs() <= 1);
+        }
+    }
+
+    #[test]
+    fn movement_uses_elapsed_time() {
+        let (_, output, mut performer) = performer(Config::default());
+
+        let now = Instant::now();
+        let mut input = ScriptedInput::new(vec![0]);
+        input.axis(0, Axis::LeftStickX, 1.0);
+        performer.step_at(&mut input, now).unwrap();
+        output.take();
+
+        // a late tick moves further than an on time one
+        performer
+            .step_at(&mut input, now + Duration::from_millis(TICK_TIME_MS))
+            .unwrap();
+        performer
+            .step_at(&mut input, now + Duration::from_millis(TICK_TIME_MS * 4))
+            .unwrap();
+        let moves = output.take();
+        match moves.as_slice() {
+            [OutputEvent::Move(on_time, 0), OutputEvent::Move(late, 0)] => {
+                // up to a pixel of remainder is carried between ticks
+                assert!((late - on_time * 3).abs() <= 3, "{:?}", moves)
+            }
+            _ => panic!("unexpected {:?}", moves),
+        }
+    }
+
+    #[test]
+    fn left_stick_moves_cursor() {
+        let (_, output, mut performer) = performer(Config::default());
+
+        let mut input = ScriptedInput::new(vec![0]);
+        let now = Instant::now();
+        input.axis(0, Axis::LeftStickX, 1.0);
+        run_ticks(&mut performer, &mut input, now, 100);
+
+        let (mut dx, mut dy) = (0, 0);
+        for event in output.take() {
+            match event {
+                OutputEvent::Move(x, y) => {
+                    dx += x;
+                    dy += y;
+                }
+                other => panic!("unexpected event {:?}", other),
+            }
+        }
+        // 70 speed * 0.02 units per ms for 100 ticks of 4 ms
+        assert!((559..=560).contains(&dx), "moved {}", dx);
+        assert_eq!(dy, 0);
+    }
+}
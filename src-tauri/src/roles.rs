@@ -0,0 +1,295 @@
+use crate::actions::Action;
+use crate::stick::Vec2;
+use rdev::Key;
+use std::f32::consts::TAU;
+
+// how far a stick is pushed before a radial sector is selected
+const PRESS_THRESHOLD: f32 = 0.5;
+// how close to the centre a stick returns before the radial selection fires
+const RADIAL_RELEASE: f32 = 0.25;
+
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone, PartialEq)]
+pub enum StickRole {
+    #[serde(rename = "cursor")]
+    Cursor,
+    #[serde(rename = "scroll")]
+    Scroll,
+    #[serde(rename = "arrow_keys")]
+    ArrowKeys,
+    #[serde(rename = "wasd")]
+    Wasd,
+    #[serde(rename = "keys")]
+    Keys(KeyStick),
+    // actions for sectors going clockwise from up, the selected one fires when the stick is let go
+    #[serde(rename = "radial")]
+    Radial(Vec<Vec<Action>>),
+    #[serde(rename = "none")]
+    None,
+}
+
+impl StickRole {
+    pub fn key_stick(&self) -> Option<&KeyStick> {
+        match self {
+            Self::ArrowKeys => Some(&ARROW_KEYS),
+            Self::Wasd => Some(&WASD_KEYS),
+            Self::Keys(keys) => Some(keys),
+            _ => None,
+        }
+    }
+}
+
+// a stick that presses keys for the direction it's pushed in
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone, PartialEq)]
+pub struct KeyStick {
+    pub up: Key,
+    pub right: Key,
+    pub down: Key,
+    pub left: Key,
+    #[serde(default = "directions_default")]
+    pub directions: u8, // 4, or 8 to press two keys on the diagonals
+    #[serde(default = "threshold_default")]
+    pub threshold: f32, // how far the stick is pushed before keys are pressed
+    // how far past a boundary the stick has to go before the keys change,
+    // in stick deflection when letting go and in sectors when turning
+    #[serde(default = "hysteresis_default")]
+    pub hysteresis: f32,
+}
+
+const fn directions_default() -> u8 {
+    4
+}
+
+const fn threshold_default() -> f32 {
+    0.5
+}
+
+const fn hysteresis_default() -> f32 {
+    0.1
+}
+
+pub const ARROW_KEYS: KeyStick = KeyStick {
+    up: Key::UpArrow,
+    right: Key::RightArrow,
+    down: Key::DownArrow,
+    left: Key::LeftArrow,
+    directions: directions_default(),
+    threshold: threshold_default(),
+    hysteresis: hysteresis_default(),
+};
+
+pub const WASD_KEYS: KeyStick = KeyStick {
+    up: Key::KeyW,
+    right: Key::KeyD,
+    down: Key::KeyS,
+    left: Key::KeyA,
+    directions: directions_default(),
+    threshold: threshold_default(),
+    hysteresis: hysteresis_default(),
+};
+
+impl KeyStick {
+    fn sectors(&self) -> usize {
+        if self.directions == 8 {
+            8
+        } else {
+            4
+        }
+    }
+
+    // the keys held for a sector, clockwise from up
+    fn sector_keys(&self, sector: usize) -> Vec<Key> {
+        if self.sectors() == 4 {
+            return vec![[self.up, self.right, self.down, self.left][sector % 4]];
+        }
+        match sector % 8 {
+            0 => vec![self.up],
+            1 => vec![self.up, self.right],
+            2 => vec![self.right],
+            3 => vec![self.down, self.right],
+            4 => vec![self.down],
+            5 => vec![self.down, self.left],
+            6 => vec![self.left],
+            _ => vec![self.up, self.left],
+        }
+    }
+}
+
+// what a stick is currently holding down or has selected
+#[derive(Debug, Default)]
+pub struct StickState {
+    keys: Vec<Key>,        // direction keys held down
+    sector: Option<usize>, // sector the keys are held for
+    radial: Option<usize>, // selected radial sector
+}
+
+impl StickState {
+    // returns the keys to release and the keys to press so the held keys match the stick
+    pub fn update_keys(&mut self, stick: Vec2<f32>, keys: &KeyStick) -> (Vec<Key>, Vec<Key>) {
+        let n = keys.sectors();
+        let magnitude = magnitude(stick);
+        self.sector = match self.sector.filter(|&s| s < n) {
+            // stay in the held sector until the stick is clearly out of it
+            Some(current) if magnitude >= keys.threshold - keys.hysteresis => {
+                let offset = (position(stick, n) - current as f32).rem_euclid(n as f32);
+                if offset.min(n as f32 - offset) <= 0.5 + keys.hysteresis {
+                    Some(current)
+                } else {
+                    Some(sector(stick, n))
+                }
+            }
+            _ if magnitude >= keys.threshold => Some(sector(stick, n)),
+            _ => None,
+        };
+        let wanted = self.sector.map(|s| keys.sector_keys(s)).unwrap_or_default();
+
+        let release = self
+            .keys
+            .iter()
+            .filter(|k| !wanted.contains(k))
+            .copied()
+            .collect();
+        let press = wanted
+            .iter()
+            .filter(|k| !self.keys.contains(k))
+            .copied()
+            .collect();
+        self.keys = wanted;
+        (release, press)
+    }
+
+    pub fn release_keys(&mut self) -> Vec<Key> {
+        self.sector = None;
+        std::mem::take(&mut self.keys)
+    }
+
+    // selects a sector while the stick is pushed, returning it once the stick is let go
+    pub fn update_radial(&mut self, stick: Vec2<f32>, sectors: usize) -> Option<usize> {
+        let magnitude = magnitude(stick);
+        if sectors == 0 {
+            self.radial = None;
+        } else if magnitude >= PRESS_THRESHOLD {
+            self.radial = Some(sector(stick, sectors));
+        } else if magnitude < RADIAL_RELEASE {
+            return self.radial.take();
+        }
+        None
+    }
+
+    pub fn selected(&self) -> Option<usize> {
+        self.radial
+    }
+
+    pub fn cancel_radial(&mut self) {
+        self.radial = None;
+    }
+}
+
+fn magnitude(stick: Vec2<f32>) -> f32 {
+    stick.x.hypot(stick.y)
+}
+
+// the stick's angle in sectors, clockwise from up
+fn position(stick: Vec2<f32>, n: usize) -> f32 {
+    stick.x.atan2(stick.y).rem_euclid(TAU) / (TAU / n as f32)
+}
+
+// which of `n` equal sectors the stick points into, clockwise with 0 centred on up
+pub fn sector(stick: Vec2<f32>, n: usize) -> usize {
+    (position(stick, n).round() as usize) % n
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn v(x: f32, y: f32) -> Vec2<f32> {
+        Vec2 { x, y }
+    }
+
+    #[test]
+    fn sectors_go_clockwise_from_up() {
+        assert_eq!(sector(v(0.0, 1.0), 4), 0);
+        assert_eq!(sector(v(1.0, 0.0), 4), 1);
+        assert_eq!(sector(v(0.0, -1.0), 4), 2);
+        assert_eq!(sector(v(-1.0, 0.0), 4), 3);
+        assert_eq!(sector(v(-0.1, 1.0), 4), 0);
+        assert_eq!(sector(v(1.0, 1.0), 8), 1);
+    }
+
+    #[test]
+    fn keys_follow_the_stick() {
+        let mut state = StickState::default();
+        assert_eq!(
+            state.update_keys(v(0.0, 0.2), &ARROW_KEYS),
+            (vec![], vec![])
+        );
+        assert_eq!(
+            state.update_keys(v(0.0, 0.8), &ARROW_KEYS),
+            (vec![], vec![Key::UpArrow])
+        );
+        assert_eq!(
+            state.update_keys(v(0.1, 0.9), &ARROW_KEYS),
+            (vec![], vec![])
+        );
+        assert_eq!(
+            state.update_keys(v(0.9, 0.0), &ARROW_KEYS),
+            (vec![Key::UpArrow], vec![Key::RightArrow])
+        );
+        assert_eq!(state.release_keys(), vec![Key::RightArrow]);
+    }
+
+    #[test]
+    fn diagonals_press_two_keys() {
+        let keys = KeyStick {
+            directions: 8,
+            ..WASD_KEYS
+        };
+        let mut state = StickState::default();
+        assert_eq!(
+            state.update_keys(v(-0.7, -0.7), &keys),
+            (vec![], vec![Key::KeyS, Key::KeyA])
+        );
+        assert_eq!(
+            state.update_keys(v(-1.0, 0.0), &keys),
+            (vec![Key::KeyS], vec![])
+        );
+    }
+
+    #[test]
+    fn hysteresis_stops_chatter() {
+        let mut state = StickState::default();
+        state.update_keys(v(0.0, 0.6), &ARROW_KEYS);
+
+        // just below the threshold and just past the sector edge keeps the key
+        assert_eq!(
+            state.update_keys(v(0.0, 0.45), &ARROW_KEYS),
+            (vec![], vec![])
+        );
+        let edge = (TAU / 8.0 + 0.1).sin_cos();
+        assert_eq!(
+            state.update_keys(v(edge.0, edge.1), &ARROW_KEYS),
+            (vec![], vec![])
+        );
+
+        // further out switches
+        let past = (TAU / 8.0 + 0.3).sin_cos();
+        assert_eq!(
+            state.update_keys(v(past.0, past.1), &ARROW_KEYS),
+            (vec![Key::UpArrow], vec![Key::RightArrow])
+        );
+        assert_eq!(
+            state.update_keys(v(0.3, 0.0), &ARROW_KEYS),
+            (vec![Key::RightArrow], vec![])
+        );
+    }
+
+    #[test]
+    fn radial_fires_when_let_go() {
+        let mut state = StickState::default();
+        assert_eq!(state.update_radial(v(0.0, -1.0), 4), None);
+        assert_eq!(state.selected(), Some(2));
+        assert_eq!(state.update_radial(v(0.0, -0.4), 4), None);
+        assert_eq!(state.update_radial(v(0.0, 0.0), 4), Some(2));
+        assert_eq!(state.update_radial(v(0.0, 0.0), 4), None);
+    }
+}
@@ -1,12 +1,14 @@
-use crate::config::Config;
+use crate::config::{Config, ConfigError};
 use crate::perform;
+use crate::watch;
 use std::sync::{Arc, Mutex};
 use std::thread;
 use tauri::menu::MenuId;
-use tauri::{menu, Listener, Manager};
+use tauri::{menu, Emitter, Listener, Manager};
 
 pub enum MenuButton {
     Hide,
+    Passthrough,
     Quit,
 }
 
@@ -14,6 +16,7 @@ impl From<MenuButton> for MenuId {
     fn from(val: MenuButton) -> Self {
         match val {
             MenuButton::Hide => "hide".into(),
+            MenuButton::Passthrough => "passthrough".into(),
             MenuButton::Quit => "quit".into(),
         }
     }
@@ -25,6 +28,7 @@ impl TryFrom<&MenuId> for MenuButton {
     fn try_from(value: &MenuId) -> Result<Self, Self::Error> {
         match value.as_ref() {
             "hide" => Ok(MenuButton::Hide),
+            "passthrough" => Ok(MenuButton::Passthrough),
             "quit" => Ok(MenuButton::Quit),
             _ => Err("Unknown menu button"),
         }
@@ -50,8 +54,20 @@ pub fn setup(
             Err(e) => {
                 log::error!("Could not load config {:?}", e);
                 log::info!("Using default config");
-                if let Err(e) = config_mtx.lock().unwrap().save() {
-                    log::error!("Could not save default config {:?}", e);
+                let mut config = config_mtx.lock().unwrap();
+                let missing = e
+                    .downcast_ref::<std::io::Error>()
+                    .map_or(false, |e| e.kind() == std::io::ErrorKind::NotFound);
+                match e.downcast::<ConfigError>() {
+                    // leave the user's file to fix, saves are refused until it loads
+                    Ok(error) => config.load_error = Some(error),
+                    // only a config that isn't there yet is replaced with the defaults
+                    Err(_) if missing => {
+                        if let Err(e) = config.save() {
+                            log::error!("Could not save default config {:?}", e);
+                        }
+                    }
+                    Err(_) => {}
                 }
             }
         }
@@ -66,9 +82,13 @@ pub fn setup(
         let save_event_config = config_mtx.clone();
         let _save_event = app.listen_any("save_config", move |_| {
             let config = save_event_config.lock().unwrap();
-            config.save().unwrap();
+            if let Err(e) = config.save() {
+                log::error!("Could not save config {:?}", e);
+            }
         });
 
+        watch::watch(app.app_handle().clone(), config_mtx.clone());
+
         let webview_window = app.get_webview_window("main").unwrap();
         let thread_config = Arc::clone(&config_mtx);
         thread::spawn(move || {
@@ -76,13 +96,24 @@ pub fn setup(
         });
 
         let hide = menu::CheckMenuItemBuilder::with_id(MenuButton::Hide, "Hide").build(app)?;
+        let passthrough =
+            menu::CheckMenuItemBuilder::with_id(MenuButton::Passthrough, "Controller mode")
+                .build(app)?;
         let quit = menu::MenuItemBuilder::with_id(MenuButton::Quit, "Quit").build(app)?;
         let tray_menu = menu::MenuBuilder::new(app)
-            .items(&[&hide])
+            .items(&[&hide, &passthrough])
             .separator()
             .items(&[&quit])
             .build()?;
 
+        // keep the checkbox in sync when the mode is toggled from the gamepad
+        let mode_event_item = passthrough.clone();
+        let _mode_event = app.listen_any("mode_change", move |msg| {
+            let passthrough: bool = msg.payload().parse().unwrap_or(false);
+            let _ = mode_event_item.set_checked(passthrough);
+        });
+
+        let menu_config = config_mtx.clone();
         let tray_icon_image =
             tauri::image::Image::new(include_bytes!("../icons/128x128.png"), 128, 128);
 
@@ -100,6 +131,11 @@ pub fn setup(
                         }
                     }
                 }
+                Ok(MenuButton::Passthrough) => {
+                    let checked = passthrough.is_checked().unwrap_or(false);
+                    menu_config.lock().unwrap().passthrough = checked;
+                    let _ = app.emit("mode_change", checked);
+                }
                 Ok(MenuButton::Quit) => {
                     log::debug!("Quitting...");
                     app.exit(0);
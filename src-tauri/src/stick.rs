@@ -0,0 +1,315 @@
+use crate::roles::StickRole;
+
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum Stick {
+    #[serde(rename = "left")]
+    Left,
+    #[serde(rename = "right")]
+    Right,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Vec2<T> {
+    pub x: T,
+    pub y: T,
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone)]
+pub struct StickConfig {
+    #[serde(default)]
+    pub role: Option<StickRole>, // what the stick does, the default depends on the stick
+    #[serde(default = "inner_deadzone_default")]
+    pub inner_deadzone: f32, // deflection ignored around the centre
+    #[serde(default = "outer_deadzone_default")]
+    pub outer_deadzone: f32, // deflection at the edge treated as fully pushed
+    #[serde(default)]
+    pub deadzone_shape: DeadzoneShape,
+    #[serde(default)]
+    pub curve: ResponseCurve, // maps stick deflection to output speed
+}
+
+const fn inner_deadzone_default() -> f32 {
+    0.1
+}
+
+const fn outer_deadzone_default() -> f32 {
+    0.05
+}
+
+impl Default for StickConfig {
+    fn default() -> Self {
+        Self {
+            role: None,
+            inner_deadzone: inner_deadzone_default(),
+            outer_deadzone: outer_deadzone_default(),
+            deadzone_shape: DeadzoneShape::default(),
+            curve: ResponseCurve::default(),
+        }
+    }
+}
+
+impl StickConfig {
+    // applies the deadzones and then the response curve to a raw stick position
+    pub fn apply(&self, stick: Vec2<f32>) -> Vec2<f32> {
+        match self.deadzone_shape {
+            DeadzoneShape::Radial => {
+                let magnitude = stick.x.hypot(stick.y);
+                let scaled = self.rescale(magnitude);
+                if scaled == 0.0 {
+                    return Vec2::default();
+                }
+                let factor = self.curve.apply(scaled) / magnitude;
+                Vec2 {
+                    x: stick.x * factor,
+                    y: stick.y * factor,
+                }
+            }
+            DeadzoneShape::Axial => Vec2 {
+                x: self
+                    .curve
+                    .apply(self.rescale(stick.x.abs()).copysign(stick.x)),
+                y: self
+                    .curve
+                    .apply(self.rescale(stick.y.abs()).copysign(stick.y)),
+            },
+        }
+    }
+
+    // maps a deflection between the deadzones onto [0, 1]
+    // so movement starts from zero at the inner deadzone edge
+    fn rescale(&self, magnitude: f32) -> f32 {
+        let inner = self.inner_deadzone.max(0.0);
+        let outer = (1.0 - self.outer_deadzone).min(1.0);
+        if magnitude <= inner {
+            0.0
+        } else if magnitude >= outer || outer <= inner {
+            1.0
+        } else {
+            (magnitude - inner) / (outer - inner)
+        }
+    }
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum DeadzoneShape {
+    // deadzone applied to the distance from the centre
+    #[serde(rename = "radial")]
+    Radial,
+    // deadzone applied to each axis separately
+    #[serde(rename = "axial")]
+    Axial,
+}
+
+impl Default for DeadzoneShape {
+    fn default() -> Self {
+        Self::Radial
+    }
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone, PartialEq)]
+pub enum ResponseCurve {
+    #[serde(rename = "linear")]
+    Linear,
+    // deflection raised to the given exponent
+    #[serde(rename = "power")]
+    Power(f32),
+    // slow near the centre and the edge, steeper in between
+    #[serde(rename = "s_curve")]
+    SCurve(f32),
+    // (deflection, output) points sorted by deflection, linearly interpolated
+    // (0, 0) and (1, 1) are implied at either end
+    #[serde(rename = "custom")]
+    Custom(Vec<(f32, f32)>),
+}
+
+impl Default for ResponseCurve {
+    fn default() -> Self {
+        Self::Linear
+    }
+}
+
+impl ResponseCurve {
+    // maps a deflection in [-1, 1] onto [-1, 1], keeping the sign
+    pub fn apply(&self, x: f32) -> f32 {
+        let magnitude = x.abs().min(1.0);
+        if magnitude == 0.0 {
+            return 0.0;
+        }
+
+        let out = match self {
+            ResponseCurve::Linear => magnitude,
+            ResponseCurve::Power(exponent) => magnitude.powf(*exponent),
+            ResponseCurve::SCurve(steepness) => {
+                let a = magnitude.powf(*steepness);
+                let b = (1.0 - magnitude).powf(*steepness);
+                a / (a + b)
+            }
+            ResponseCurve::Custom(points) => piecewise(points, magnitude),
+        };
+
+        out.clamp(0.0, 1.0).copysign(x)
+    }
+
+    // catches curves that parse but can't be applied, returning what's wrong with them
+    pub fn validate(&self) -> Result<(), &'static str> {
+        let positive = |v: f32| v.is_finite() && v > 0.0;
+        let in_range = |v: f32| (0.0..=1.0).contains(&v);
+        match self {
+            ResponseCurve::Linear => Ok(()),
+            ResponseCurve::Power(exponent) if !positive(*exponent) => {
+                Err("power curve exponent must be above 0")
+            }
+            ResponseCurve::SCurve(steepness) if !positive(*steepness) => {
+                Err("s_curve steepness must be above 0")
+            }
+            ResponseCurve::Power(_) | ResponseCurve::SCurve(_) => Ok(()),
+            ResponseCurve::Custom(points) => {
+                if !points.iter().all(|&(x, y)| in_range(x) && in_range(y)) {
+                    return Err("custom curve points must be between 0 and 1");
+                }
+                if points.windows(2).any(|pair| pair[0].0 > pair[1].0) {
+                    return Err("custom curve points must be sorted by deflection");
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+fn piecewise(points: &[(f32, f32)], x: f32) -> f32 {
+    let mut prev = (0.0, 0.0);
+    for &(px, py) in points.iter().chain(std::iter::once(&(1.0, 1.0))) {
+        if x <= px {
+            if px <= prev.0 {
+                return py;
+            }
+            return prev.1 + (py - prev.1) * (x - prev.0) / (px - prev.0);
+        }
+        prev = (px, py);
+    }
+    prev.1
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn approx(a: f32, b: f32) -> bool {
+        (a - b).abs() < 1e-5
+    }
+
+    #[test]
+    fn linear_is_identity() {
+        for x in [-1.0, -0.3, 0.0, 0.25, 1.0] {
+            assert!(approx(ResponseCurve::Linear.apply(x), x));
+        }
+    }
+
+    #[test]
+    fn invalid_curves_are_caught() {
+        assert!(ResponseCurve::Power(0.0).validate().is_err());
+        assert!(ResponseCurve::Power(f32::NAN).validate().is_err());
+        assert!(ResponseCurve::SCurve(0.0).validate().is_err());
+        assert!(ResponseCurve::Custom(vec![(0.6, 0.2), (0.3, 0.5)])
+            .validate()
+            .is_err());
+        assert!(ResponseCurve::Custom(vec![(0.5, 1.5)]).validate().is_err());
+        assert!(
+            ResponseCurve::Custom(vec![(0.3, 0.1), (0.3, 0.5), (0.8, 0.9)])
+                .validate()
+                .is_ok()
+        );
+    }
+
+    #[test]
+    fn input_is_clamped() {
+        assert!(approx(ResponseCurve::Linear.apply(1.5), 1.0));
+        assert!(approx(ResponseCurve::Power(2.0).apply(-3.0), -1.0));
+    }
+
+    #[test]
+    fn power_keeps_sign() {
+        let curve = ResponseCurve::Power(2.0);
+        assert!(approx(curve.apply(0.5), 0.25));
+        assert!(approx(curve.apply(-0.5), -0.25));
+        assert!(approx(curve.apply(1.0), 1.0));
+    }
+
+    #[test]
+    fn s_curve_is_symmetric_around_half() {
+        let curve = ResponseCurve::SCurve(3.0);
+        assert!(approx(curve.apply(0.5), 0.5));
+        assert!(curve.apply(0.2) < 0.2);
+        assert!(curve.apply(0.8) > 0.8);
+        assert!(approx(curve.apply(0.2) + curve.apply(0.8), 1.0));
+    }
+
+    #[test]
+    fn custom_interpolates_between_points() {
+        let curve = ResponseCurve::Custom(vec![(0.5, 0.1), (0.9, 0.5)]);
+        assert!(approx(curve.apply(0.25), 0.05));
+        assert!(approx(curve.apply(0.5), 0.1));
+        assert!(approx(curve.apply(0.7), 0.3));
+        assert!(approx(curve.apply(0.95), 0.75));
+        assert!(approx(curve.apply(-0.7), -0.3));
+    }
+
+    fn stick(inner_deadzone: f32, outer_deadzone: f32, shape: DeadzoneShape) -> StickConfig {
+        StickConfig {
+            inner_deadzone,
+            outer_deadzone,
+            deadzone_shape: shape,
+            curve: ResponseCurve::Linear,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn radial_deadzone_ignores_small_deflection() {
+        let config = stick(0.2, 0.0, DeadzoneShape::Radial);
+        let out = config.apply(Vec2 { x: 0.1, y: 0.1 });
+        assert_eq!(out, Vec2::default());
+    }
+
+    #[test]
+    fn radial_deadzone_rescales_from_edge() {
+        let config = stick(0.2, 0.2, DeadzoneShape::Radial);
+        // just past the inner edge starts near zero
+        let out = config.apply(Vec2 { x: 0.21, y: 0.0 });
+        assert!(out.x > 0.0 && out.x < 0.02);
+        // halfway between the zones is half speed
+        let out = config.apply(Vec2 { x: 0.0, y: -0.5 });
+        assert!(approx(out.y, -0.5));
+        // the outer zone saturates
+        let out = config.apply(Vec2 { x: 0.85, y: 0.0 });
+        assert!(approx(out.x, 1.0));
+    }
+
+    #[test]
+    fn radial_deadzone_keeps_direction() {
+        let config = stick(0.1, 0.0, DeadzoneShape::Radial);
+        let out = config.apply(Vec2 { x: 0.6, y: 0.8 });
+        assert!(approx(out.x / out.y, 0.75));
+    }
+
+    #[test]
+    fn axial_deadzone_is_per_axis() {
+        let config = stick(0.2, 0.0, DeadzoneShape::Axial);
+        // drift on one axis doesn't leak into the other
+        let out = config.apply(Vec2 { x: 0.9, y: 0.15 });
+        assert_eq!(out.y, 0.0);
+        assert!(approx(out.x, 0.875));
+    }
+
+    #[test]
+    fn curve_round_trips_through_toml() {
+        let config: StickConfig = toml::from_str("curve = { custom = [[0.5, 0.1]] }").unwrap();
+        assert_eq!(config.curve, ResponseCurve::Custom(vec![(0.5, 0.1)]));
+
+        let config: StickConfig = toml::from_str("curve = { power = 2.5 }").unwrap();
+        assert_eq!(config.curve, ResponseCurve::Power(2.5));
+
+        let config: StickConfig = toml::from_str("").unwrap();
+        assert_eq!(config.curve, ResponseCurve::Linear);
+    }
+}
@@ -0,0 +1,176 @@
+use crate::macros::MacroStep;
+use crate::output::{OutputError, OutputSink};
+use rdev::Key;
+
+// the key that types a character on a US layout and whether it needs shift
+pub fn char_key(c: char) -> Option<(Key, bool)> {
+    let key = match c {
+        'a' | 'A' => Key::KeyA,
+        'b' | 'B' => Key::KeyB,
+        'c' | 'C' => Key::KeyC,
+        'd' | 'D' => Key::KeyD,
+        'e' | 'E' => Key::KeyE,
+        'f' | 'F' => Key::KeyF,
+        'g' | 'G' => Key::KeyG,
+        'h' | 'H' => Key::KeyH,
+        'i' | 'I' => Key::KeyI,
+        'j' | 'J' => Key::KeyJ,
+        'k' | 'K' => Key::KeyK,
+        'l' | 'L' => Key::KeyL,
+        'm' | 'M' => Key::KeyM,
+        'n' | 'N' => Key::KeyN,
+        'o' | 'O' => Key::KeyO,
+        'p' | 'P' => Key::KeyP,
+        'q' | 'Q' => Key::KeyQ,
+        'r' | 'R' => Key::KeyR,
+        's' | 'S' => Key::KeyS,
+        't' | 'T' => Key::KeyT,
+        'u' | 'U' => Key::KeyU,
+        'v' | 'V' => Key::KeyV,
+        'w' | 'W' => Key::KeyW,
+        'x' | 'X' => Key::KeyX,
+        'y' | 'Y' => Key::KeyY,
+        'z' | 'Z' => Key::KeyZ,
+        '1' | '!' => Key::Num1,
+        '2' | '@' => Key::Num2,
+        '3' | '#' => Key::Num3,
+        '4' | '$' => Key::Num4,
+        '5' | '%' => Key::Num5,
+        '6' | '^' => Key::Num6,
+        '7' | '&' => Key::Num7,
+        '8' | '*' => Key::Num8,
+        '9' | '(' => Key::Num9,
+        '0' | ')' => Key::Num0,
+        '`' | '~' => Key::BackQuote,
+        '-' | '_' => Key::Minus,
+        '=' | '+' => Key::Equal,
+        '[' | '{' => Key::LeftBracket,
+        ']' | '}' => Key::RightBracket,
+        '\\' | '|' => Key::BackSlash,
+        ';' | ':' => Key::SemiColon,
+        '\'' | '"' => Key::Quote,
+        ',' | '<' => Key::Comma,
+        '.' | '>' => Key::Dot,
+        '/' | '?' => Key::Slash,
+        ' ' => Key::Space,
+        '\t' => Key::Tab,
+        '\n' => Key::Return,
+        _ => return None,
+    };
+    let shift = c.is_ascii_uppercase() || "~!@#$%^&*()_+{}|:\"<>?".contains(c);
+    Some((key, shift))
+}
+
+// types the character as unicode if the output supports it, falling back to key presses
+pub fn type_char(output: &dyn OutputSink, c: char) -> Result<(), OutputError> {
+    // control characters are only understood as real key presses
+    if !c.is_control() {
+        match output.type_unicode(c) {
+            Err(OutputError::Unsupported(_)) => {}
+            result => return result,
+        }
+    }
+
+    let (key, shift) = char_key(c).ok_or(OutputError::Unsupported(c))?;
+
+    if shift {
+        output.key_down(Key::ShiftLeft)?;
+    }
+    output.key_down(key)?;
+    output.key_up(key)?;
+    if shift {
+        output.key_up(Key::ShiftLeft)?;
+    }
+    Ok(())
+}
+
+// fails on the first character the output has no way of typing,
+// so text is refused up front instead of being typed with gaps
+pub fn check_typable(output: &dyn OutputSink, text: &str) -> Result<(), OutputError> {
+    let unicode = output.types_unicode();
+    match text
+        .chars()
+        .find(|&c| char_key(c).is_none() && (c.is_control() || !unicode))
+    {
+        Some(c) => Err(OutputError::Unsupported(c)),
+        None => Ok(()),
+    }
+}
+
+// one type step per character with a wait in between, so typing can be cancelled
+pub fn type_steps(text: &str, delay_ms: u64) -> Vec<MacroStep> {
+    let mut steps = vec![];
+    for c in text.chars() {
+        if delay_ms > 0 && !steps.is_empty() {
+            steps.push(MacroStep::Wait(delay_ms));
+        }
+        steps.push(MacroStep::Type(c.to_string()));
+    }
+    steps
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::output::{OutputEvent, RecordingOutput};
+
+    #[test]
+    fn types_ascii_with_keys() {
+        let output = RecordingOutput::default();
+        for c in "a!".chars() {
+            type_char(&output, c).unwrap();
+        }
+        assert_eq!(
+            output.take(),
+            vec![
+                OutputEvent::KeyDown(Key::KeyA),
+                OutputEvent::KeyUp(Key::KeyA),
+                OutputEvent::KeyDown(Key::ShiftLeft),
+                OutputEvent::KeyDown(Key::Num1),
+                OutputEvent::KeyUp(Key::Num1),
+                OutputEvent::KeyUp(Key::ShiftLeft),
+            ]
+        );
+    }
+
+    #[test]
+    fn refuses_unmapped_without_unicode() {
+        let output = RecordingOutput::default();
+        assert!(type_char(&output, '\u{e9}').is_err());
+        assert!(check_typable(&output, "caf\u{e9}").is_err());
+        assert!(check_typable(&output, "cafe").is_ok());
+        assert!(check_typable(&RecordingOutput::with_unicode(), "caf\u{e9}").is_ok());
+        assert_eq!(output.take(), vec![]);
+    }
+
+    #[test]
+    fn types_unicode_when_supported() {
+        let output = RecordingOutput::with_unicode();
+        for c in "a\u{e9}\u{1f600}\n".chars() {
+            type_char(&output, c).unwrap();
+        }
+        assert_eq!(
+            output.take(),
+            vec![
+                OutputEvent::Unicode('a'),
+                OutputEvent::Unicode('\u{e9}'),
+                OutputEvent::Unicode('\u{1f600}'),
+                OutputEvent::KeyDown(Key::Return),
+                OutputEvent::KeyUp(Key::Return),
+            ]
+        );
+    }
+
+    #[test]
+    fn type_steps_wait_between_characters() {
+        assert_eq!(
+            type_steps("ab", 5),
+            vec![
+                MacroStep::Type("a".to_string()),
+                MacroStep::Wait(5),
+                MacroStep::Type("b".to_string()),
+            ]
+        );
+        assert_eq!(type_steps("ab", 0).len(), 2);
+    }
+}
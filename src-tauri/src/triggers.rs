@@ -0,0 +1,175 @@
+use crate::stick::ResponseCurve;
+use gilrs::Button;
+
+// how far below the threshold a pressed trigger has to go before it's released
+const PRESS_HYSTERESIS: f32 = 0.05;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Trigger {
+    Left,
+    Right,
+}
+
+impl Trigger {
+    pub fn from_button(button: Button) -> Option<Self> {
+        match button {
+            Button::LeftTrigger2 => Some(Self::Left),
+            Button::RightTrigger2 => Some(Self::Right),
+            _ => None,
+        }
+    }
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone, PartialEq)]
+pub enum TriggerRole {
+    // acts as a button once pulled past the threshold
+    #[serde(rename = "press")]
+    Press,
+    // multiplies the cursor speed, up to the given factor when fully pulled
+    #[serde(rename = "speed")]
+    Speed(f32),
+    // scrolls this many notches per second when fully pulled, negative scrolls down
+    #[serde(rename = "scroll")]
+    Scroll(f32),
+    #[serde(rename = "none")]
+    None,
+}
+
+impl Default for TriggerRole {
+    fn default() -> Self {
+        Self::Press
+    }
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone)]
+pub struct TriggerConfig {
+    #[serde(default)]
+    pub role: TriggerRole,
+    #[serde(default = "threshold_default")]
+    pub threshold: f32, // how far the trigger is pulled before it counts as pressed
+    #[serde(default = "deadzone_default")]
+    pub deadzone: f32, // pull ignored before analog roles respond
+    #[serde(default)]
+    pub curve: ResponseCurve,
+}
+
+const fn threshold_default() -> f32 {
+    0.5
+}
+
+const fn deadzone_default() -> f32 {
+    0.05
+}
+
+impl Default for TriggerConfig {
+    fn default() -> Self {
+        Self {
+            role: TriggerRole::default(),
+            threshold: threshold_default(),
+            deadzone: deadzone_default(),
+            curve: ResponseCurve::default(),
+        }
+    }
+}
+
+impl TriggerConfig {
+    // applies the deadzone and response curve to a raw trigger value in [0, 1]
+    pub fn apply(&self, value: f32) -> f32 {
+        let deadzone = self.deadzone.clamp(0.0, 1.0);
+        if value <= deadzone {
+            return 0.0;
+        }
+        if deadzone >= 1.0 {
+            return 1.0;
+        }
+        self.curve
+            .apply(((value - deadzone) / (1.0 - deadzone)).min(1.0))
+    }
+
+    // whether a trigger at `value` should be pressed, given whether it already is
+    pub fn pressed(&self, value: f32, was_pressed: bool) -> bool {
+        if self.role != TriggerRole::Press {
+            return false;
+        }
+        if was_pressed {
+            value >= self.threshold - PRESS_HYSTERESIS
+        } else {
+            value >= self.threshold
+        }
+    }
+
+    pub fn speed_mult(&self, value: f32) -> f32 {
+        match self.role {
+            TriggerRole::Speed(max) => 1.0 + (max - 1.0) * self.apply(value),
+            _ => 1.0,
+        }
+    }
+
+    // notches per second
+    pub fn scroll_speed(&self, value: f32) -> f32 {
+        match self.role {
+            TriggerRole::Scroll(speed) => speed * self.apply(value),
+            _ => 0.0,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn role(role: TriggerRole) -> TriggerConfig {
+        TriggerConfig {
+            role,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn deadzone_rescales() {
+        let trigger = TriggerConfig {
+            deadzone: 0.2,
+            ..Default::default()
+        };
+        assert_eq!(trigger.apply(0.1), 0.0);
+        assert!((trigger.apply(0.6) - 0.5).abs() < 1e-6);
+        assert_eq!(trigger.apply(1.0), 1.0);
+    }
+
+    #[test]
+    fn press_has_hysteresis() {
+        let trigger = role(TriggerRole::Press);
+        assert!(!trigger.pressed(0.49, false));
+        assert!(trigger.pressed(0.5, false));
+        assert!(trigger.pressed(0.47, true));
+        assert!(!trigger.pressed(0.44, true));
+    }
+
+    #[test]
+    fn analog_roles_never_press() {
+        assert!(!role(TriggerRole::Speed(3.0)).pressed(1.0, false));
+        assert!(!role(TriggerRole::None).pressed(1.0, false));
+    }
+
+    #[test]
+    fn speed_scales_with_pull() {
+        let trigger = TriggerConfig {
+            deadzone: 0.0,
+            ..role(TriggerRole::Speed(3.0))
+        };
+        assert_eq!(trigger.speed_mult(0.0), 1.0);
+        assert!((trigger.speed_mult(0.5) - 2.0).abs() < 1e-6);
+        assert_eq!(trigger.speed_mult(1.0), 3.0);
+        assert_eq!(role(TriggerRole::Press).speed_mult(1.0), 1.0);
+    }
+
+    #[test]
+    fn scroll_scales_with_pull() {
+        let trigger = TriggerConfig {
+            deadzone: 0.0,
+            ..role(TriggerRole::Scroll(-20.0))
+        };
+        assert_eq!(trigger.scroll_speed(0.5), -10.0);
+        assert_eq!(role(TriggerRole::Press).scroll_speed(1.0), 0.0);
+    }
+}
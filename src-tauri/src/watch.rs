@@ -0,0 +1,67 @@
+use crate::config::{Config, ConfigError};
+use anyhow::Result;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime};
+use std::{fs, thread};
+use tauri::{AppHandle, Emitter};
+
+// how often the config directory is checked for changes
+const POLL_TIME_MS: u64 = 1000;
+
+// watches the config directory and swaps in config.toml whenever it's edited
+pub fn watch(app_handle: AppHandle, config_mx: Arc<Mutex<Config>>) {
+    let config_file = Config::with_config_file(&Config::config_dir(&app_handle));
+    thread::spawn(move || {
+        let mut last = modified(&config_file);
+        loop {
+            thread::sleep(Duration::from_millis(POLL_TIME_MS));
+            let current = modified(&config_file);
+            if current == last {
+                continue;
+            }
+            last = current;
+
+            // deleted or being replaced
+            if current.is_none() {
+                continue;
+            }
+            if let Err(e) = reload(&app_handle, &config_file, &config_mx) {
+                log::error!("Could not reload config: {:?}", e);
+            }
+        }
+    });
+}
+
+fn modified(path: &Path) -> Option<(SystemTime, u64)> {
+    let metadata = fs::metadata(path).ok()?;
+    Some((metadata.modified().ok()?, metadata.len()))
+}
+
+fn reload(app_handle: &AppHandle, path: &Path, config_mx: &Mutex<Config>) -> Result<()> {
+    let new = match fs::read_to_string(path) {
+        Ok(text) => Config::parse(&text),
+        Err(e) => Err(ConfigError::unreadable(&e)),
+    };
+    let new = match new {
+        Ok(new) => new,
+        Err(e) => {
+            // saving is refused until it's fixed
+            config_mx.lock().unwrap().load_error = Some(e.clone());
+            app_handle.emit("config_error", &e)?;
+            return Err(e.into());
+        }
+    };
+
+    let mut config = config_mx.lock().unwrap();
+    let fixed = config.load_error.take().is_some();
+    // nothing to do when it's our own save
+    if !fixed && toml::Value::try_from(&new)? == toml::Value::try_from(&*config)? {
+        return Ok(());
+    }
+
+    log::info!("Config file changed, reloading");
+    config.reload(new);
+    app_handle.emit("config_reloaded", &*config)?;
+    Ok(())
+}
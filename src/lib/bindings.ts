@@ -1,34 +1,110 @@
 import { z } from "zod";
 
-export const BasicAction = z.enum(["None", "SpeedUp", "SpeedDown", "SpeedInc", "SpeedDec", "Rumble", "ToggleVis"]);
-export const ClickAction = z.object({ Click: z.enum(["Left", "Middle", "Right"]) });
-export const KeypressAction =
-	z.object(
-		{
-			KeyPress: z.tuple([
-				z.string(),
-				z.array(z.enum(["Shift", "Ctrl", "Alt", "Meta"]))
-			])
-		}
-	);
+export const BasicAction = z.enum([
+	"speed_inc",
+	"speed_dec",
+	"speed_up",
+	"speed_down",
+	"rumble",
+	"toggle_vis",
+	"toggle_mode",
+	"swap_sticks",
+]);
+export const ClickAction = z.object({ click: z.enum(["Left", "Middle", "Right"]) });
+export const KeypressAction = z.object({
+	keypress: z.object({
+		key: z.union([z.string(), z.object({ Unknown: z.number() })]),
+		modifiers: z.array(z.enum(["Alt", "Ctrl", "Win", "Shift"])),
+	}),
+});
+// the rest, like macros and layers, aren't edited here so they're passed through as is
+export const OtherAction = z.record(z.string(), z.unknown());
 export type BasicActionType = z.infer<typeof BasicAction>;
 export type ClickActionType = z.infer<typeof ClickAction>;
 export type KeypressActionType = z.infer<typeof KeypressAction>;
 
-const Action = z.union([BasicAction, KeypressAction, ClickAction,]);
+const Action = z.union([BasicAction, KeypressAction, ClickAction, OtherAction]);
 
 export type ActionType = z.infer<typeof Action>;
 
+// a short readable form of an action as the backend serializes it
+export const describeAction = (action: unknown) => {
+	if (typeof action === "string") return action;
+	return Object.entries(action as Record<string, unknown>)
+		.map(([name, value]) => `${name} ${JSON.stringify(value)}`)
+		.join(", ");
+};
+
+export const ResponseCurve = z.union([
+	z.literal("linear"),
+	z.object({ power: z.number() }),
+	z.object({ s_curve: z.number() }),
+	z.object({ custom: z.array(z.tuple([z.number(), z.number()])) }),
+]);
+export type ResponseCurveType = z.infer<typeof ResponseCurve>;
+
+export const StickRole = z.union([
+	z.enum(["cursor", "scroll", "arrow_keys", "wasd", "none"]),
+	z.object({
+		keys: z.object({
+			up: z.string(),
+			right: z.string(),
+			down: z.string(),
+			left: z.string(),
+			directions: z.number(),
+			threshold: z.number(),
+			hysteresis: z.number(),
+		}),
+	}),
+	z.object({ radial: z.array(z.array(z.unknown())) }),
+]);
+export type StickRoleType = z.infer<typeof StickRole>;
+
+export const StickConfig = z.object({
+	role: StickRole.nullish(),
+	inner_deadzone: z.number(),
+	outer_deadzone: z.number(),
+	deadzone_shape: z.enum(["radial", "axial"]),
+	curve: ResponseCurve,
+});
+export type StickConfigType = z.infer<typeof StickConfig>;
+
+export const GamepadInfo = z.object({
+	id: z.number(),
+	name: z.string(),
+	uuid: z.string(),
+	ff_supported: z.boolean(),
+});
+export type GamepadInfoType = z.infer<typeof GamepadInfo>;
+
+export const Backup = z.object({
+	name: z.string(),
+	time_ms: z.number(),
+});
+export type BackupType = z.infer<typeof Backup>;
+
+export const ConfigError = z.object({
+	message: z.string(),
+	line: z.number().nullable(),
+	column: z.number().nullable(),
+});
+export type ConfigErrorType = z.infer<typeof ConfigError>;
+
 export const Config = z.object({
 	speed: z.number(),
 	speed_up: z.number(),
 	speed_down: z.number(),
 	speed_step: z.number(),
+	gamepad_uuid: z.string().nullish(),
+	left_stick: StickConfig,
+	right_stick: StickConfig,
 	actions: z.object({
 		South: Action.array(),
 		East: Action.array(),
 		West: Action.array(),
 		North: Action.array(),
+		C: Action.array(),
+		Z: Action.array(),
 		DPadUp: Action.array(),
 		DPadDown: Action.array(),
 		DPadLeft: Action.array(),
@@ -41,6 +117,7 @@ export const Config = z.object({
 		RightThumb: Action.array(),
 		Start: Action.array(),
 		Select: Action.array(),
+		Mode: Action.array(),
 	}).partial()
 });
 